@@ -0,0 +1,195 @@
+use crate::http::{date, negotiate, percent, Request, Response, StatusCode};
+use std::cmp::Ordering;
+use std::fs;
+use std::io::Result as IoResult;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Directory listings for folders under the public path, as an HTML page or,
+// for clients that ask for it with "Accept: application/json", as JSON.
+// The sort order comes from the query string: ?sort=name|size|modified&order=asc|desc
+
+struct Entry {
+  name: String,
+  is_dir: bool,
+  size: u64,
+  modified: SystemTime,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum SortKey {
+  Name,
+  Size,
+  Modified,
+}
+
+pub fn render(request: &Request, dir: &Path, show_dotfiles: bool) -> Response {
+  let mut entries = match list(dir, show_dotfiles) {
+    Ok(entries) => entries,
+    Err(e) => {
+      println!("Failed to list directory {}: {}", dir.display(), e);
+      return Response::new(StatusCode::InternalServerError, None);
+    }
+  };
+
+  let query = request.query_string();
+  let key = match query.and_then(|q| q.get("sort")).map(|v| v.first()) {
+    Some("size") => SortKey::Size,
+    Some("modified") => SortKey::Modified,
+    _ => SortKey::Name
+  };
+  let descending = query.and_then(|q| q.get("order")).map(|v| v.first()) == Some("desc");
+  sort(&mut entries, key, descending);
+
+  let accept = request.headers().get("Accept");
+  let (body, content_type) = match negotiate::preferred_media_type(accept, &["text/html", "application/json"]) {
+    Some("application/json") => (to_json(&entries), "application/json"),
+    _ => (to_html(request.path(), &entries, key, descending), "text/html; charset=utf-8")
+  };
+
+  let mut response = Response::new(StatusCode::Ok, Some(body.into_bytes()));
+  response.set_header("Content-Type", content_type);
+  // The same URL gives HTML or JSON depending on the Accept header.
+  response.add_vary("Accept");
+  response
+}
+
+fn list(dir: &Path, show_dotfiles: bool) -> IoResult<Vec<Entry>> {
+  let mut entries = Vec::new();
+
+  for entry in fs::read_dir(dir)? {
+    let entry = entry?;
+    let name = entry.file_name().to_string_lossy().into_owned();
+    if name.starts_with('.') && !show_dotfiles {
+      continue;
+    }
+
+    // fs::metadata follows symbolic links, so links show what they point to.
+    let metadata = match fs::metadata(entry.path()) {
+      Ok(metadata) => metadata,
+      Err(_) => continue
+    };
+
+    entries.push(Entry {
+      name,
+      is_dir: metadata.is_dir(),
+      size: metadata.len(),
+      modified: metadata.modified().unwrap_or(UNIX_EPOCH),
+    });
+  }
+
+  Ok(entries)
+}
+
+// Directories always come first, the order only applies within each group.
+fn sort(entries: &mut [Entry], key: SortKey, descending: bool) {
+  entries.sort_by(|a, b| {
+    let order = match key {
+      SortKey::Name => a.name.to_lowercase().cmp(&b.name.to_lowercase()),
+      SortKey::Size => a.size.cmp(&b.size),
+      SortKey::Modified => a.modified.cmp(&b.modified),
+    }
+    .then_with(|| a.name.cmp(&b.name));
+
+    let order = if descending { order.reverse() } else { order };
+    match (a.is_dir, b.is_dir) {
+      (true, false) => Ordering::Less,
+      (false, true) => Ordering::Greater,
+      _ => order
+    }
+  });
+}
+
+fn to_html(path: &str, entries: &[Entry], key: SortKey, descending: bool) -> String {
+  let base = if path.ends_with('/') { path.to_string() } else { format!("{}/", path) };
+  let title = escape_html(&base);
+  let base_href: Vec<_> = base.split('/').map(percent::encode_path_segment).collect();
+  let base_href = escape_html(&base_href.join("/"));
+
+  // Clicking the column that is already sorted flips the order.
+  let header_link = |column: SortKey, name: &str, label: &str| {
+    let order = if column == key && !descending { "desc" } else { "asc" };
+    format!("<th><a href=\"?sort={}&amp;order={}\">{}</a></th>", name, order, label)
+  };
+
+  let mut html = format!(
+    "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"UTF-8\" />\n<title>Index of {}</title>\n</head>\n<body>\n<h1>Index of {}</h1>\n<table>\n<tr>{}{}{}</tr>\n",
+    title,
+    title,
+    header_link(SortKey::Name, "name", "Name"),
+    header_link(SortKey::Size, "size", "Size"),
+    header_link(SortKey::Modified, "modified", "Last modified")
+  );
+
+  if base != "/" {
+    html.push_str("<tr><td><a href=\"../\">../</a></td><td></td><td></td></tr>\n");
+  }
+
+  for entry in entries {
+    // The name goes into the URL percent-encoded and into the page HTML-escaped,
+    // a file called "<script>.html" must not become markup.
+    let suffix = if entry.is_dir { "/" } else { "" };
+    let href = format!("{}{}{}", base_href, escape_html(&percent::encode_path_segment(&entry.name)), suffix);
+    let size = if entry.is_dir { "-".to_string() } else { entry.size.to_string() };
+
+    html.push_str(&format!(
+      "<tr><td><a href=\"{}\">{}{}</a></td><td>{}</td><td>{}</td></tr>\n",
+      href,
+      escape_html(&entry.name),
+      suffix,
+      size,
+      date::format(entry.modified)
+    ));
+  }
+
+  html.push_str("</table>\n</body>\n</html>\n");
+  html
+}
+
+fn to_json(entries: &[Entry]) -> String {
+  let items: Vec<String> = entries
+    .iter()
+    .map(|entry| {
+      format!(
+        "{{\"name\":\"{}\",\"type\":\"{}\",\"size\":{},\"modified\":\"{}\"}}",
+        escape_json(&entry.name),
+        if entry.is_dir { "directory" } else { "file" },
+        entry.size,
+        date::format(entry.modified)
+      )
+    })
+    .collect();
+
+  format!("[{}]", items.join(","))
+}
+
+fn escape_html(s: &str) -> String {
+  let mut escaped = String::with_capacity(s.len());
+  for c in s.chars() {
+    match c {
+      '&' => escaped.push_str("&amp;"),
+      '<' => escaped.push_str("&lt;"),
+      '>' => escaped.push_str("&gt;"),
+      '"' => escaped.push_str("&quot;"),
+      '\'' => escaped.push_str("&#39;"),
+      c => escaped.push(c)
+    }
+  }
+  escaped
+}
+
+fn escape_json(s: &str) -> String {
+  let mut escaped = String::with_capacity(s.len());
+  for c in s.chars() {
+    match c {
+      '"' => escaped.push_str("\\\""),
+      '\\' => escaped.push_str("\\\\"),
+      '\n' => escaped.push_str("\\n"),
+      '\r' => escaped.push_str("\\r"),
+      '\t' => escaped.push_str("\\t"),
+      c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+      c => escaped.push(c)
+    }
+  }
+  escaped
+}
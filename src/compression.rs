@@ -0,0 +1,240 @@
+use crate::http::{negotiate, Request, Response, StatusCode};
+use super::deflate;
+use super::middleware::Middleware;
+use super::server::Handler;
+
+// Below this size the compressed response is barely smaller, if at all,
+// and not worth the time.
+const DEFAULT_MIN_SIZE: u64 = 1024;
+// Larger files are sent as they are, straight from the file, instead of being read
+// into memory to compress them. A precompressed copy next to them serves them best.
+const MAX_SIZE: u64 = 8 * 1024 * 1024;
+
+// Types that compress well, images, fonts and archives are compressed already.
+const DEFAULT_CONTENT_TYPES: [&str; 8] = [
+  "text/",
+  "application/json",
+  "application/javascript",
+  "application/xml",
+  "application/manifest+json",
+  "application/wasm",
+  "image/svg+xml",
+  "font/ttf"
+];
+
+// Compresses responses with gzip or deflate when the client's Accept-Encoding allows it:
+//
+// Accept-Encoding: gzip, deflate, br;q=0.9
+//
+// Streamed bodies are sent as they are.
+pub struct Compression {
+  min_size: u64,
+  content_types: Vec<String>
+}
+
+impl Compression {
+  pub fn new() -> Self {
+    Self {
+      min_size: DEFAULT_MIN_SIZE,
+      content_types: DEFAULT_CONTENT_TYPES.iter().map(|content_type| content_type.to_string()).collect()
+    }
+  }
+
+  // Bodies smaller than this many bytes are sent uncompressed.
+  pub fn min_size(mut self, min_size: u64) -> Self {
+    self.min_size = min_size;
+    self
+  }
+
+  // The Content-Types that get compressed, an entry ending with "/" like "text/"
+  // stands for the whole type.
+  pub fn content_types(mut self, content_types: &[&str]) -> Self {
+    self.content_types = content_types.iter().map(|content_type| content_type.to_ascii_lowercase()).collect();
+    self
+  }
+
+  fn compresses(&self, content_type: &str) -> bool {
+    // Parameters like "; charset=utf-8" don't matter here.
+    let media_type = content_type.split(';').next().unwrap_or("").trim().to_ascii_lowercase();
+    self.content_types.iter().any(|allowed| match allowed.ends_with('/') {
+      true => media_type.starts_with(allowed.as_str()),
+      false => media_type == *allowed
+    })
+  }
+}
+
+impl Default for Compression {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl Middleware for Compression {
+  fn handle(&self, request: &Request, next: &dyn Handler) -> Response {
+    let mut response = next.handle_request(request);
+    let not_modified = response.status_code() == StatusCode::NotModified;
+
+    // A 206 is a part of the uncompressed representation, compressing it would
+    // leave the client with pieces it can't put together.
+    if response.status_code() == StatusCode::PartialContent
+      || response.header("Content-Encoding").is_some()
+      || response.header("Cache-Control").is_some_and(|value| value.to_ascii_lowercase().contains("no-transform"))
+      || !response.header("Content-Type").is_some_and(|content_type| self.compresses(content_type)) {
+      return response;
+    }
+
+    // A 304 has no body to measure. It has to carry the Vary and ETag of the 200
+    // it stands for, which is taken to be a compressed one.
+    if !not_modified && !response.body_len().is_some_and(|len| len >= self.min_size && len <= MAX_SIZE) {
+      return response;
+    }
+
+    // Whether it is compressed or not, the response depends on Accept-Encoding.
+    response.add_vary("Accept-Encoding");
+
+    let accept_encoding = request.headers().get_all("Accept-Encoding").collect::<Vec<_>>().join(",");
+    let encoding = match negotiate::preferred_encoding(&accept_encoding, &["gzip", "deflate"]) {
+      Some(encoding) => encoding,
+      _ => return response
+    };
+
+    if not_modified {
+      weaken_etag(&mut response);
+      return response;
+    }
+
+    // HEAD requests reach us as GET, see Request::is_head(). They are compressed
+    // all the same, only that gives them the Content-Length the GET response has,
+    // at the cost of reading and compressing a body that is then thrown away.
+    // MAX_SIZE bounds that cost.
+    if let Err(e) = response.buffer_body() {
+      println!("Failed to read the response body: {}", e);
+      return response;
+    }
+    let body = response.body().unwrap_or_default();
+    let compressed = match encoding {
+      "gzip" => deflate::gzip(body),
+      // The deflate coding is zlib's format, not raw DEFLATE, despite its name.
+      _ => deflate::zlib(body)
+    };
+
+    if compressed.len() >= body.len() {
+      return response;
+    }
+
+    response.set_body(compressed);
+    response.set_header("Content-Encoding", encoding);
+    weaken_etag(&mut response);
+    response
+  }
+}
+
+// The compressed bytes aren't the ones the ETag was made for. A weak ETag still
+// lets If-None-Match revalidate the cached copy, but keeps If-Range from
+// mixing the bytes of the two.
+fn weaken_etag(response: &mut Response) {
+  if let Some(etag) = response.header("ETag").filter(|etag| !etag.starts_with("W/")) {
+    let weak = format!("W/{}", etag);
+    response.set_header("ETag", &weak);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::convert::TryFrom;
+
+  const ETAG: &str = "\"12a024-5f3\"";
+
+  // Runs a request with these headers through Compression, in front of a
+  // handler that answers with the response make() returns.
+  fn compress(headers: &str, make: impl Fn() -> Response + Send + Sync) -> Response {
+    let buf = format!("GET /style.css HTTP/1.1\r\nHost: localhost\r\n{}\r\n", headers);
+    let request = Request::try_from(buf.as_bytes()).unwrap();
+    let handler = move |_: &Request| make();
+    Compression::new().handle(&request, &handler)
+  }
+
+  fn css(len: usize) -> Response {
+    let mut response = Response::new(StatusCode::Ok, Some(b"body { margin: 0 }\n".repeat(len / 19 + 1)[..len].to_vec()));
+    response.set_header("Content-Type", "text/css");
+    response.set_header("ETag", ETAG);
+    response
+  }
+
+  fn not_modified() -> Response {
+    let mut response = Response::new(StatusCode::NotModified, None);
+    response.set_header("Content-Type", "text/css");
+    response.set_header("ETag", ETAG);
+    response
+  }
+
+  #[test]
+  fn compresses_with_the_preferred_encoding() {
+    let response = compress("Accept-Encoding: deflate, gzip;q=0.5\r\n", || css(4096));
+    assert_eq!(response.header("Content-Encoding"), Some("deflate"));
+    assert_eq!(response.body().unwrap()[0], 0x78);
+
+    let response = compress("Accept-Encoding: gzip\r\n", || css(4096));
+    assert_eq!(response.header("Content-Encoding"), Some("gzip"));
+    assert_eq!(response.header("Vary"), Some("Accept-Encoding"));
+    assert_eq!(response.header("ETag"), Some("W/\"12a024-5f3\""));
+    let body = response.body().unwrap();
+    assert_eq!(body[..2], [0x1f, 0x8b]);
+    assert!(body.len() < 4096);
+  }
+
+  #[test]
+  fn leaves_responses_it_should_not_compress() {
+    let uncompressed = |response: Response| {
+      assert_ne!(response.header("Content-Encoding"), Some("gzip"));
+      assert_eq!(response.header("ETag"), Some(ETAG));
+    };
+
+    uncompressed(compress("Accept-Encoding: gzip\r\n", || css(100)));
+    uncompressed(compress("Accept-Encoding: gzip\r\n", || {
+      let mut response = css(4096);
+      response.set_header("Content-Type", "image/png");
+      response
+    }));
+    uncompressed(compress("Accept-Encoding: gzip\r\n", || {
+      let mut response = css(4096);
+      response.set_header("Cache-Control", "no-transform");
+      response
+    }));
+    uncompressed(compress("Accept-Encoding: gzip\r\n", || {
+      let mut response = css(4096);
+      response.set_header("Content-Encoding", "br");
+      response
+    }));
+  }
+
+  #[test]
+  fn varies_even_when_the_client_accepts_no_encoding() {
+    let response = compress("", || css(4096));
+    assert_eq!(response.header("Content-Encoding"), None);
+    assert_eq!(response.header("Vary"), Some("Accept-Encoding"));
+    assert_eq!(response.header("ETag"), Some(ETAG));
+
+    let response = compress("Accept-Encoding: identity\r\n", || css(4096));
+    assert_eq!(response.header("Content-Encoding"), None);
+  }
+
+  #[test]
+  fn not_modified_matches_the_compressed_response() {
+    let compressed = compress("Accept-Encoding: gzip\r\n", || css(4096));
+    let response = compress("Accept-Encoding: gzip\r\nIf-None-Match: W/\"12a024-5f3\"\r\n", not_modified);
+    assert_eq!(response.status_code(), StatusCode::NotModified);
+    assert_eq!(response.header("ETag"), compressed.header("ETag"));
+    assert_eq!(response.header("Vary"), compressed.header("Vary"));
+    assert_eq!(response.header("Content-Encoding"), None);
+  }
+
+  #[test]
+  fn not_modified_matches_the_uncompressed_response() {
+    let uncompressed = compress("", || css(4096));
+    let response = compress("If-None-Match: \"12a024-5f3\"\r\n", not_modified);
+    assert_eq!(response.header("ETag"), uncompressed.header("ETag"));
+    assert_eq!(response.header("Vary"), uncompressed.header("Vary"));
+  }
+}
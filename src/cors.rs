@@ -0,0 +1,208 @@
+use crate::http::{Method, Request, Response, StatusCode};
+use super::middleware::Middleware;
+use super::server::Handler;
+use std::time::Duration;
+
+// Cross-Origin Resource Sharing. Browsers only let a script on https://app.example.com
+// read a response from another origin if that response allows it with the
+// Access-Control-* headers.
+//
+// Requests that aren't "simple", a PUT or a JSON POST for example, are preceded by a
+// preflight: an OPTIONS request asking whether the real one would be allowed.
+//
+// OPTIONS /api/users HTTP/1.1
+// Origin: https://app.example.com
+// Access-Control-Request-Method: PUT
+// Access-Control-Request-Headers: content-type
+//
+// Cors answers preflights itself, the handler never sees them. Other requests go
+// to the handler and their responses get the headers added.
+pub struct Cors {
+  origins: Vec<Origin>,
+  methods: Vec<Method>,
+  headers: Vec<String>,
+  expose_headers: Vec<String>,
+  credentials: bool,
+  max_age: Option<Duration>
+}
+
+enum Origin {
+  Any,
+  Exact(String),
+  // An origin with a "*" in it, https://*.example.com matches every subdomain.
+  Wildcard(String, String),
+  Predicate(Box<dyn Fn(&str) -> bool + Send + Sync>)
+}
+
+impl Origin {
+  fn matches(&self, origin: &str) -> bool {
+    match self {
+      Self::Any => true,
+      // Origins are scheme, host and port, which compare case-insensitively.
+      Self::Exact(allowed) => allowed.eq_ignore_ascii_case(origin),
+      Self::Wildcard(prefix, suffix) => {
+        origin.len() > prefix.len() + suffix.len()
+          && origin.get(..prefix.len()).is_some_and(|start| start.eq_ignore_ascii_case(prefix))
+          && origin.get(origin.len() - suffix.len()..).is_some_and(|end| end.eq_ignore_ascii_case(suffix))
+      },
+      Self::Predicate(predicate) => predicate(origin)
+    }
+  }
+}
+
+impl Cors {
+  // Allows no origins until some are added, with the methods browsers
+  // send without a preflight.
+  pub fn new() -> Self {
+    Self {
+      origins: Vec::new(),
+      methods: vec![Method::GET, Method::HEAD, Method::POST],
+      headers: Vec::new(),
+      expose_headers: Vec::new(),
+      credentials: false,
+      max_age: None
+    }
+  }
+
+  // Allows an origin like "https://app.example.com". A "*" matches any part of
+  // it, "https://*.example.com" allows every subdomain and "*" every origin.
+  pub fn allow_origin(mut self, origin: &str) -> Self {
+    let origin = origin.trim_end_matches('/');
+    let allowed = match origin.split_once('*') {
+      Some(("", "")) => Origin::Any,
+      Some((prefix, suffix)) => Origin::Wildcard(prefix.to_string(), suffix.to_string()),
+      None => Origin::Exact(origin.to_string())
+    };
+    self.origins.push(allowed);
+    self
+  }
+
+  // Allows the origins the function returns true for.
+  pub fn allow_origin_fn(mut self, predicate: impl Fn(&str) -> bool + Send + Sync + 'static) -> Self {
+    self.origins.push(Origin::Predicate(Box::new(predicate)));
+    self
+  }
+
+  pub fn allow_methods(mut self, methods: &[Method]) -> Self {
+    self.methods = methods.to_vec();
+    self
+  }
+
+  // The request headers scripts may send, besides the ones every browser
+  // allows like Accept or Content-Language.
+  pub fn allow_headers(mut self, headers: &[&str]) -> Self {
+    self.headers = headers.iter().map(|header| header.to_string()).collect();
+    self
+  }
+
+  // The response headers scripts may read, besides the ones every browser
+  // allows like Content-Type or Cache-Control.
+  pub fn expose_headers(mut self, headers: &[&str]) -> Self {
+    self.expose_headers = headers.iter().map(|header| header.to_string()).collect();
+    self
+  }
+
+  // Lets scripts send cookies and read responses to requests that carry them.
+  pub fn allow_credentials(mut self, credentials: bool) -> Self {
+    self.credentials = credentials;
+    self
+  }
+
+  // How long browsers may cache the answer to a preflight.
+  pub fn max_age(mut self, max_age: Duration) -> Self {
+    self.max_age = Some(max_age);
+    self
+  }
+
+  fn allows_origin(&self, origin: &str) -> bool {
+    self.origins.iter().any(|allowed| allowed.matches(origin))
+  }
+
+  // "*" can't be used with credentials, browsers then need the origin itself.
+  fn varies_by_origin(&self) -> bool {
+    self.credentials || !self.origins.iter().any(|allowed| matches!(allowed, Origin::Any))
+  }
+
+  // Access-Control-Request-Headers: content-type, x-request-id
+  fn allows_headers(&self, requested: &str) -> bool {
+    requested
+      .split(',')
+      .map(str::trim)
+      .filter(|header| !header.is_empty())
+      .all(|header| self.headers.iter().any(|allowed| allowed.eq_ignore_ascii_case(header)))
+  }
+
+  fn preflight(&self, origin: &str, request: &Request, requested_method: &str) -> Response {
+    let method_allowed = requested_method.parse::<Method>().is_ok_and(|method| self.methods.contains(&method));
+    let requested_headers = request.headers().get_all("Access-Control-Request-Headers").collect::<Vec<_>>().join(",");
+
+    if !self.allows_origin(origin) || !method_allowed || !self.allows_headers(&requested_headers) {
+      println!("Refused CORS preflight from {} for {} {}", origin, requested_method, request.path());
+      return Response::new(StatusCode::Forbidden, None);
+    }
+
+    let mut response = Response::new(StatusCode::NoContent, None);
+    self.allow(&mut response, origin);
+    if self.varies_by_origin() {
+      response.add_vary("Origin");
+    }
+
+    let methods: Vec<&str> = self.methods.iter().map(|method| method.as_str()).collect();
+    response.set_header("Access-Control-Allow-Methods", &methods.join(", "));
+    if !self.headers.is_empty() {
+      response.set_header("Access-Control-Allow-Headers", &self.headers.join(", "));
+    }
+    if let Some(max_age) = self.max_age {
+      response.set_header("Access-Control-Max-Age", &max_age.as_secs().to_string());
+    }
+    response
+  }
+
+  // The headers that go on both preflights and actual responses.
+  fn allow(&self, response: &mut Response, origin: &str) {
+    if self.varies_by_origin() {
+      response.set_header("Access-Control-Allow-Origin", origin);
+    } else {
+      response.set_header("Access-Control-Allow-Origin", "*");
+    }
+
+    if self.credentials {
+      response.set_header("Access-Control-Allow-Credentials", "true");
+    }
+  }
+}
+
+impl Default for Cors {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl Middleware for Cors {
+  fn handle(&self, request: &Request, next: &dyn Handler) -> Response {
+    // Requests from the same origin, and from clients that aren't browsers, have no Origin.
+    let origin = match request.headers().get("Origin") {
+      Some(origin) => origin,
+      None => return next.handle_request(request)
+    };
+
+    if *request.method() == Method::OPTIONS {
+      if let Some(requested_method) = request.headers().get("Access-Control-Request-Method") {
+        return self.preflight(origin, request, requested_method);
+      }
+    }
+
+    let mut response = next.handle_request(request);
+    // Caches must not hand a response meant for one origin to a page from another.
+    if self.varies_by_origin() {
+      response.add_vary("Origin");
+    }
+    if self.allows_origin(origin) {
+      self.allow(&mut response, origin);
+      if !self.expose_headers.is_empty() {
+        response.set_header("Access-Control-Expose-Headers", &self.expose_headers.join(", "));
+      }
+    }
+    response
+  }
+}
@@ -0,0 +1,817 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+// A DEFLATE compressor (RFC 1951), and the gzip (RFC 1952) and zlib (RFC 1950)
+// formats around it, which are what the gzip and deflate content codings send.
+//
+// DEFLATE works in two steps. LZ77 replaces repeated bytes with a reference to an
+// earlier copy, "<p>hello</p><p>" becomes "<p>hello</" plus "copy 3 bytes from 10
+// bytes back". Then Huffman coding gives the symbols that come up often shorter
+// codes than the ones that are rare.
+
+const WINDOW_SIZE: usize = 32768;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+const HASH_BITS: u32 = 15;
+// How many earlier positions with the same first three bytes are compared before
+// settling for the best match so far. More finds longer matches but takes longer.
+const MAX_CHAIN: usize = 128;
+// A match at least this long is taken right away, without checking whether the
+// next position has a longer one.
+const LAZY_LIMIT: usize = 32;
+// A three byte match far back can cost more bits than the three literals.
+const TOO_FAR: usize = 4096;
+// Every block gets its own Huffman codes, shorter blocks adapt to changes in the data.
+const BLOCK_TOKENS: usize = 16384;
+const MAX_STORED: usize = 65535;
+const END_OF_BLOCK: usize = 256;
+
+const LENGTH_BASE: [u16; 29] = [
+  3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258
+];
+const LENGTH_EXTRA: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+const DIST_BASE: [u16; 30] = [
+  1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097,
+  6145, 8193, 12289, 16385, 24577
+];
+const DIST_EXTRA: [u8; 30] = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+// The order the code length code lengths are sent in, the ones that are usually unused come last.
+const CODE_LENGTH_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+// Compresses the data into a gzip file, for Content-Encoding: gzip.
+pub fn gzip(data: &[u8]) -> Vec<u8> {
+  // Magic number, DEFLATE, no flags, no modification time, no extra flags, unknown OS.
+  let mut out = vec![0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 255];
+  out.extend_from_slice(&compress(data));
+  out.extend_from_slice(&crc32(data).to_le_bytes());
+  out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+  out
+}
+
+// Compresses the data into a zlib stream, for Content-Encoding: deflate.
+pub fn zlib(data: &[u8]) -> Vec<u8> {
+  // DEFLATE with a 32K window, then flags that make the two bytes a multiple of 31.
+  let mut out = vec![0x78, 0x9c];
+  out.extend_from_slice(&compress(data));
+  out.extend_from_slice(&adler32(data).to_be_bytes());
+  out
+}
+
+// Compresses the data into raw DEFLATE blocks.
+pub fn compress(data: &[u8]) -> Vec<u8> {
+  let mut writer = BitWriter::default();
+  let mut matcher = Matcher::new(data);
+  let mut tokens = Vec::with_capacity(BLOCK_TOKENS);
+  let mut start = 0;
+
+  loop {
+    tokens.clear();
+    let end = matcher.tokens(start, &mut tokens);
+    let last = end >= data.len();
+    write_block(&mut writer, &tokens, &data[start..end], last);
+    if last {
+      break;
+    }
+    start = end;
+  }
+
+  writer.finish()
+}
+
+#[derive(Clone, Copy)]
+enum Token {
+  Literal(u8),
+  // Copy length bytes from distance bytes back.
+  Match { length: u16, distance: u16 }
+}
+
+// Finds repeated bytes. Every position is filed under a hash of the three bytes that
+// start there, so earlier positions starting with the same bytes are quick to find.
+struct Matcher<'a> {
+  data: &'a [u8],
+  // The last position with each hash.
+  head: Vec<usize>,
+  // The position before it with the same hash, for every position in the window.
+  prev: Vec<usize>
+}
+
+impl<'a> Matcher<'a> {
+  fn new(data: &'a [u8]) -> Self {
+    Self { data, head: vec![usize::MAX; 1 << HASH_BITS], prev: vec![usize::MAX; WINDOW_SIZE] }
+  }
+
+  fn hash(&self, pos: usize) -> usize {
+    let bytes = &self.data[pos..pos + MIN_MATCH];
+    ((bytes[0] as usize) << 10 ^ (bytes[1] as usize) << 5 ^ bytes[2] as usize) & ((1 << HASH_BITS) - 1)
+  }
+
+  fn insert(&mut self, pos: usize) {
+    if pos + MIN_MATCH > self.data.len() {
+      return;
+    }
+    let hash = self.hash(pos);
+    self.prev[pos % WINDOW_SIZE] = self.head[hash];
+    self.head[hash] = pos;
+  }
+
+  // The longest earlier copy of the bytes at pos, as (length, distance).
+  fn longest_match(&self, pos: usize) -> (usize, usize) {
+    if pos + MIN_MATCH > self.data.len() {
+      return (0, 0);
+    }
+
+    let max_length = MAX_MATCH.min(self.data.len() - pos);
+    let (mut best_length, mut best_distance) = (0, 0);
+    let mut candidate = self.head[self.hash(pos)];
+
+    for _ in 0..MAX_CHAIN {
+      // Positions only chain to earlier ones, and only the last 32K bytes can be referred to.
+      if candidate == usize::MAX || pos - candidate >= WINDOW_SIZE {
+        break;
+      }
+
+      // A longer match has to differ from the best one at its last byte.
+      if self.data[candidate + best_length] == self.data[pos + best_length] {
+        let length = self.data[candidate..candidate + max_length]
+          .iter()
+          .zip(&self.data[pos..pos + max_length])
+          .take_while(|(a, b)| a == b)
+          .count();
+
+        if length > best_length {
+          best_length = length;
+          best_distance = pos - candidate;
+          if length == max_length {
+            break;
+          }
+        }
+      }
+      candidate = self.prev[candidate % WINDOW_SIZE];
+    }
+
+    if best_length < MIN_MATCH || (best_length == MIN_MATCH && best_distance > TOO_FAR) {
+      return (0, 0);
+    }
+    (best_length, best_distance)
+  }
+
+  // Turns the data from pos on into tokens until the block is full, returns where it stopped.
+  fn tokens(&mut self, mut pos: usize, tokens: &mut Vec<Token>) -> usize {
+    while pos < self.data.len() && tokens.len() < BLOCK_TOKENS {
+      let (length, distance) = self.longest_match(pos);
+      self.insert(pos);
+
+      if length == 0 {
+        tokens.push(Token::Literal(self.data[pos]));
+        pos += 1;
+        continue;
+      }
+
+      // Lazy matching, when the next position has a longer match it is better to
+      // send this byte as a literal and take that one.
+      if length < LAZY_LIMIT && self.longest_match(pos + 1).0 > length {
+        tokens.push(Token::Literal(self.data[pos]));
+        pos += 1;
+        continue;
+      }
+
+      tokens.push(Token::Match { length: length as u16, distance: distance as u16 });
+      for skipped in pos + 1..pos + length {
+        self.insert(skipped);
+      }
+      pos += length;
+    }
+    pos
+  }
+}
+
+// Writes bits the way DEFLATE packs them, starting at the least significant bit of each byte.
+#[derive(Default)]
+struct BitWriter {
+  out: Vec<u8>,
+  bits: u64,
+  count: u32
+}
+
+impl BitWriter {
+  fn write(&mut self, value: u32, count: u32) {
+    self.bits |= (value as u64) << self.count;
+    self.count += count;
+    while self.count >= 8 {
+      self.out.push(self.bits as u8);
+      self.bits >>= 8;
+      self.count -= 8;
+    }
+  }
+
+  fn align(&mut self) {
+    if self.count > 0 {
+      self.write(0, 8 - self.count);
+    }
+  }
+
+  fn finish(mut self) -> Vec<u8> {
+    self.align();
+    self.out
+  }
+}
+
+// A Huffman code: the length of each symbol's code, and the code itself with its
+// bits reversed, since Huffman codes are packed starting with their first bit.
+struct Huffman {
+  lengths: Vec<u8>,
+  codes: Vec<u16>
+}
+
+impl Huffman {
+  fn from_lengths(lengths: Vec<u8>) -> Self {
+    let codes = canonical_codes(&lengths);
+    Self { lengths, codes }
+  }
+
+  // The codes RFC 1951 defines for blocks that don't send their own.
+  fn fixed_literals() -> Self {
+    let lengths = (0..288).map(|symbol| match symbol {
+      0..=143 => 8,
+      144..=255 => 9,
+      256..=279 => 7,
+      _ => 8
+    }).collect();
+    Self::from_lengths(lengths)
+  }
+
+  fn fixed_distances() -> Self {
+    Self::from_lengths(vec![5; 30])
+  }
+
+  fn write(&self, writer: &mut BitWriter, symbol: usize) {
+    writer.write(self.codes[symbol] as u32, self.lengths[symbol] as u32);
+  }
+
+  // How many bits the symbols with these frequencies take with this code.
+  fn cost(&self, freqs: &[u32]) -> u64 {
+    freqs.iter().zip(&self.lengths).map(|(&freq, &length)| freq as u64 * length as u64).sum()
+  }
+}
+
+// Code lengths for the symbols with the given frequencies, none longer than the limit.
+// When the optimal code is too deep the frequencies are flattened until it isn't,
+// which costs a little compression but is much simpler than an exact algorithm.
+fn code_lengths(freqs: &[u32], limit: u8) -> Vec<u8> {
+  let mut freqs = freqs.to_vec();
+  loop {
+    let lengths = huffman_lengths(&freqs);
+    if lengths.iter().all(|&length| length <= limit) {
+      return lengths;
+    }
+    for freq in freqs.iter_mut().filter(|freq| **freq > 0) {
+      *freq = (*freq / 2).max(1);
+    }
+  }
+}
+
+// Builds the Huffman tree by joining the two rarest nodes until one is left,
+// the length of a symbol's code is its depth in the tree.
+fn huffman_lengths(freqs: &[u32]) -> Vec<u8> {
+  let mut lengths = vec![0; freqs.len()];
+  let mut parents = vec![usize::MAX; freqs.len()];
+  let mut heap: BinaryHeap<Reverse<(u64, usize)>> = freqs
+    .iter()
+    .enumerate()
+    .filter(|(_, &freq)| freq > 0)
+    .map(|(symbol, &freq)| Reverse((freq as u64, symbol)))
+    .collect();
+
+  if heap.len() == 1 {
+    let Reverse((_, symbol)) = heap.pop().unwrap();
+    lengths[symbol] = 1;
+    return lengths;
+  }
+
+  while heap.len() > 1 {
+    let Reverse((weight_a, a)) = heap.pop().unwrap();
+    let Reverse((weight_b, b)) = heap.pop().unwrap();
+    let node = parents.len();
+    parents.push(usize::MAX);
+    parents[a] = node;
+    parents[b] = node;
+    heap.push(Reverse((weight_a + weight_b, node)));
+  }
+
+  for (symbol, length) in lengths.iter_mut().enumerate().filter(|(symbol, _)| freqs[*symbol] > 0) {
+    let mut node = symbol;
+    while parents[node] != usize::MAX {
+      node = parents[node];
+      *length += 1;
+    }
+  }
+  lengths
+}
+
+// Assigns the codes from their lengths alone, so only the lengths have to be sent:
+// shorter codes come first, and codes of the same length are in symbol order.
+fn canonical_codes(lengths: &[u8]) -> Vec<u16> {
+  let mut count = [0u32; 16];
+  for &length in lengths.iter().filter(|&&length| length > 0) {
+    count[length as usize] += 1;
+  }
+
+  let mut next_code = [0u32; 16];
+  let mut code = 0;
+  for bits in 1..16 {
+    code = (code + count[bits - 1]) << 1;
+    next_code[bits] = code;
+  }
+
+  lengths.iter().map(|&length| {
+    if length == 0 {
+      return 0;
+    }
+    let code = next_code[length as usize];
+    next_code[length as usize] += 1;
+    (code as u16).reverse_bits() >> (16 - length)
+  }).collect()
+}
+
+fn length_code(length: u16) -> usize {
+  LENGTH_BASE.partition_point(|&base| base <= length) - 1
+}
+
+fn distance_code(distance: u16) -> usize {
+  DIST_BASE.partition_point(|&base| base <= distance) - 1
+}
+
+// The code lengths of a dynamic block, run-length encoded as RFC 1951 describes:
+// 16 repeats the previous length, 17 and 18 are runs of zeros.
+// Every item is (symbol, extra bits value, number of extra bits).
+fn run_lengths(lengths: &[u8]) -> Vec<(usize, u32, u32)> {
+  let mut items = Vec::new();
+  let mut i = 0;
+
+  while i < lengths.len() {
+    let length = lengths[i];
+    let run = lengths[i..].iter().take_while(|&&l| l == length).count();
+    let mut left = run;
+
+    if length == 0 {
+      while left >= 11 {
+        let repeat = left.min(138);
+        items.push((18, (repeat - 11) as u32, 7));
+        left -= repeat;
+      }
+      if left >= 3 {
+        items.push((17, (left - 3) as u32, 3));
+        left = 0;
+      }
+    } else {
+      items.push((length as usize, 0, 0));
+      left -= 1;
+      while left >= 3 {
+        let repeat = left.min(6);
+        items.push((16, (repeat - 3) as u32, 2));
+        left -= repeat;
+      }
+    }
+    items.extend((0..left).map(|_| (length as usize, 0, 0)));
+
+    i += run;
+  }
+  items
+}
+
+// Writes the tokens as one block, with its own Huffman codes, with the fixed
+// codes, or uncompressed, whichever comes out smallest.
+fn write_block(writer: &mut BitWriter, tokens: &[Token], raw: &[u8], last: bool) {
+  let mut literal_freqs = [0u32; 286];
+  let mut distance_freqs = [0u32; 30];
+  let mut extra_bits = 0u64;
+  for token in tokens {
+    match *token {
+      Token::Literal(byte) => literal_freqs[byte as usize] += 1,
+      Token::Match { length, distance } => {
+        let (length_code, distance_code) = (length_code(length), distance_code(distance));
+        literal_freqs[257 + length_code] += 1;
+        distance_freqs[distance_code] += 1;
+        extra_bits += LENGTH_EXTRA[length_code] as u64 + DIST_EXTRA[distance_code] as u64;
+      }
+    }
+  }
+  literal_freqs[END_OF_BLOCK] = 1;
+
+  // Like zlib, make sure both codes have at least two symbols. Some decoders
+  // refuse a code with a single symbol, or none at all.
+  for freqs in [&mut literal_freqs[..], &mut distance_freqs[..]] {
+    for symbol in 0..2 {
+      if freqs.iter().filter(|&&freq| freq > 0).count() < 2 && freqs[symbol] == 0 {
+        freqs[symbol] = 1;
+      }
+    }
+  }
+
+  let literals = Huffman::from_lengths(code_lengths(&literal_freqs, 15));
+  let distances = Huffman::from_lengths(code_lengths(&distance_freqs, 15));
+  let header = DynamicHeader::new(&literals.lengths, &distances.lengths);
+  let dynamic_cost = header.cost() + literals.cost(&literal_freqs) + distances.cost(&distance_freqs) + extra_bits;
+
+  let (fixed_literals, fixed_distances) = (Huffman::fixed_literals(), Huffman::fixed_distances());
+  let fixed_cost = 3 + fixed_literals.cost(&literal_freqs) + fixed_distances.cost(&distance_freqs) + extra_bits;
+
+  // Every stored block has 5 bytes of header, after padding to a whole byte.
+  let stored_cost = (raw.len() + raw.len().div_ceil(MAX_STORED) * 5) as u64 * 8 + 10;
+
+  if !raw.is_empty() && stored_cost < dynamic_cost.min(fixed_cost) {
+    let chunks = raw.len().div_ceil(MAX_STORED);
+    for (i, chunk) in raw.chunks(MAX_STORED).enumerate() {
+      writer.write((last && i == chunks - 1) as u32, 1);
+      writer.write(0, 2);
+      writer.align();
+      writer.write(chunk.len() as u32, 16);
+      writer.write(!(chunk.len() as u16) as u32, 16);
+      for &byte in chunk {
+        writer.write(byte as u32, 8);
+      }
+    }
+  } else if fixed_cost <= dynamic_cost {
+    writer.write(last as u32, 1);
+    writer.write(1, 2);
+    write_tokens(writer, tokens, &fixed_literals, &fixed_distances);
+  } else {
+    writer.write(last as u32, 1);
+    writer.write(2, 2);
+    header.write(writer);
+    write_tokens(writer, tokens, &literals, &distances);
+  }
+}
+
+fn write_tokens(writer: &mut BitWriter, tokens: &[Token], literals: &Huffman, distances: &Huffman) {
+  for token in tokens {
+    match *token {
+      Token::Literal(byte) => literals.write(writer, byte as usize),
+      Token::Match { length, distance } => {
+        let code = length_code(length);
+        literals.write(writer, 257 + code);
+        writer.write((length - LENGTH_BASE[code]) as u32, LENGTH_EXTRA[code] as u32);
+
+        let code = distance_code(distance);
+        distances.write(writer, code);
+        writer.write((distance - DIST_BASE[code]) as u32, DIST_EXTRA[code] as u32);
+      }
+    }
+  }
+  literals.write(writer, END_OF_BLOCK);
+}
+
+// A dynamic block starts with the lengths of its codes, which are themselves
+// Huffman coded with a third, small code.
+struct DynamicHeader {
+  literal_count: usize,
+  distance_count: usize,
+  code_length_count: usize,
+  items: Vec<(usize, u32, u32)>,
+  code_lengths: Huffman
+}
+
+impl DynamicHeader {
+  fn new(literal_lengths: &[u8], distance_lengths: &[u8]) -> Self {
+    // Trailing unused symbols are left out.
+    let literal_count = 257.max(literal_lengths.iter().rposition(|&length| length > 0).map_or(0, |i| i + 1));
+    let distance_count = 1.max(distance_lengths.iter().rposition(|&length| length > 0).map_or(0, |i| i + 1));
+
+    let all_lengths = [&literal_lengths[..literal_count], &distance_lengths[..distance_count]].concat();
+    let items = run_lengths(&all_lengths);
+
+    let mut freqs = [0u32; 19];
+    for &(symbol, _, _) in &items {
+      freqs[symbol] += 1;
+    }
+    let code_lengths = Huffman::from_lengths(code_lengths(&freqs, 7));
+    let code_length_count = 4.max(CODE_LENGTH_ORDER.iter().rposition(|&symbol| code_lengths.lengths[symbol] > 0).map_or(0, |i| i + 1));
+
+    Self { literal_count, distance_count, code_length_count, items, code_lengths }
+  }
+
+  fn cost(&self) -> u64 {
+    let items: u64 = self.items
+      .iter()
+      .map(|&(symbol, _, extra_bits)| self.code_lengths.lengths[symbol] as u64 + extra_bits as u64)
+      .sum();
+    3 + 5 + 5 + 4 + 3 * self.code_length_count as u64 + items
+  }
+
+  fn write(&self, writer: &mut BitWriter) {
+    writer.write((self.literal_count - 257) as u32, 5);
+    writer.write((self.distance_count - 1) as u32, 5);
+    writer.write((self.code_length_count - 4) as u32, 4);
+    for &symbol in &CODE_LENGTH_ORDER[..self.code_length_count] {
+      writer.write(self.code_lengths.lengths[symbol] as u32, 3);
+    }
+    for &(symbol, extra, extra_bits) in &self.items {
+      self.code_lengths.write(writer, symbol);
+      writer.write(extra, extra_bits);
+    }
+  }
+}
+
+const CRC_TABLE: [u32; 256] = crc_table();
+
+const fn crc_table() -> [u32; 256] {
+  let mut table = [0; 256];
+  let mut n = 0;
+  while n < 256 {
+    let mut crc = n as u32;
+    let mut k = 0;
+    while k < 8 {
+      crc = if crc & 1 != 0 { 0xedb88320 ^ (crc >> 1) } else { crc >> 1 };
+      k += 1;
+    }
+    table[n] = crc;
+    n += 1;
+  }
+  table
+}
+
+// The checksum at the end of a gzip file.
+pub fn crc32(data: &[u8]) -> u32 {
+  !data.iter().fold(!0u32, |crc, &byte| CRC_TABLE[((crc ^ byte as u32) & 0xff) as usize] ^ (crc >> 8))
+}
+
+// The checksum at the end of a zlib stream.
+pub fn adler32(data: &[u8]) -> u32 {
+  const MOD: u32 = 65521;
+  let (mut a, mut b) = (1u32, 0u32);
+  // 5552 bytes is the most that can be summed before b could overflow.
+  for chunk in data.chunks(5552) {
+    for &byte in chunk {
+      a += byte as u32;
+      b += a;
+    }
+    a %= MOD;
+    b %= MOD;
+  }
+  b << 16 | a
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  // Just enough of an inflater to check the compressor's output, written from
+  // RFC 1951 rather than from the compressor so the two don't share mistakes.
+  // Returns the data and the type of every block, 0 stored, 1 fixed, 2 dynamic.
+  fn inflate(data: &[u8]) -> (Vec<u8>, Vec<u32>) {
+    let mut bits = Bits { data, pos: 0 };
+    let (mut out, mut types) = (Vec::new(), Vec::new());
+    loop {
+      let last = bits.read(1) == 1;
+      let block_type = bits.read(2);
+      types.push(block_type);
+      match block_type {
+        0 => {
+          bits.pos = bits.pos.div_ceil(8) * 8;
+          let len = bits.read(16);
+          assert_eq!(len, !bits.read(16) & 0xffff, "stored block length check");
+          for _ in 0..len {
+            out.push(bits.read(8) as u8);
+          }
+        },
+        1 => {
+          let mut lengths = [8u8; 288];
+          lengths[144..256].fill(9);
+          lengths[256..280].fill(7);
+          inflate_codes(&mut bits, &mut out, &Code::new(&lengths), &Code::new(&[5; 30]));
+        },
+        2 => {
+          let (literals, distances) = dynamic_codes(&mut bits);
+          inflate_codes(&mut bits, &mut out, &literals, &distances);
+        },
+        _ => panic!("reserved block type")
+      }
+      if last {
+        assert_eq!(bits.pos.div_ceil(8), data.len(), "bytes left after the last block");
+        return (out, types);
+      }
+    }
+  }
+
+  struct Bits<'a> {
+    data: &'a [u8],
+    pos: usize
+  }
+
+  impl Bits<'_> {
+    // Bits are packed starting from the least significant one.
+    fn read(&mut self, count: u32) -> u32 {
+      let mut value = 0;
+      for i in 0..count {
+        let bit = (self.data[self.pos / 8] >> (self.pos % 8)) & 1;
+        value |= (bit as u32) << i;
+        self.pos += 1;
+      }
+      value
+    }
+  }
+
+  // A canonical Huffman code, decoded a bit at a time like zlib's puff.
+  struct Code {
+    counts: [i32; 16],
+    symbols: Vec<usize>
+  }
+
+  impl Code {
+    fn new(lengths: &[u8]) -> Self {
+      let mut counts = [0; 16];
+      for &length in lengths {
+        counts[length as usize] += 1;
+      }
+      counts[0] = 0;
+      let mut symbols: Vec<usize> = (0..lengths.len()).filter(|&symbol| lengths[symbol] > 0).collect();
+      symbols.sort_by_key(|&symbol| lengths[symbol]);
+      Self { counts, symbols }
+    }
+
+    fn decode(&self, bits: &mut Bits) -> usize {
+      let (mut code, mut first, mut index) = (0, 0, 0);
+      for &count in &self.counts[1..] {
+        code |= bits.read(1) as i32;
+        if code - count < first {
+          return self.symbols[(index + code - first) as usize];
+        }
+        index += count;
+        first = (first + count) << 1;
+        code <<= 1;
+      }
+      panic!("no such code");
+    }
+  }
+
+  fn dynamic_codes(bits: &mut Bits) -> (Code, Code) {
+    let literal_count = bits.read(5) as usize + 257;
+    let distance_count = bits.read(5) as usize + 1;
+    let code_length_count = bits.read(4) as usize + 4;
+
+    let mut code_lengths = [0u8; 19];
+    for &symbol in &CODE_LENGTH_ORDER[..code_length_count] {
+      code_lengths[symbol] = bits.read(3) as u8;
+    }
+    let code_lengths = Code::new(&code_lengths);
+
+    let mut lengths = Vec::new();
+    while lengths.len() < literal_count + distance_count {
+      match code_lengths.decode(bits) {
+        length @ 0..=15 => lengths.push(length as u8),
+        16 => {
+          let previous = *lengths.last().expect("repeat with no previous length");
+          let repeat = 3 + bits.read(2) as usize;
+          lengths.extend(std::iter::repeat_n(previous, repeat));
+        },
+        17 => lengths.extend(std::iter::repeat_n(0, 3 + bits.read(3) as usize)),
+        _ => lengths.extend(std::iter::repeat_n(0, 11 + bits.read(7) as usize))
+      }
+    }
+    assert_eq!(lengths.len(), literal_count + distance_count, "run crosses the end of the lengths");
+    (Code::new(&lengths[..literal_count]), Code::new(&lengths[literal_count..]))
+  }
+
+  fn inflate_codes(bits: &mut Bits, out: &mut Vec<u8>, literals: &Code, distances: &Code) {
+    loop {
+      let symbol = literals.decode(bits);
+      if symbol < 256 {
+        out.push(symbol as u8);
+        continue;
+      }
+      if symbol == END_OF_BLOCK {
+        return;
+      }
+      let code = symbol - 257;
+      let length = LENGTH_BASE[code] as usize + bits.read(LENGTH_EXTRA[code] as u32) as usize;
+      let code = distances.decode(bits);
+      let distance = DIST_BASE[code] as usize + bits.read(DIST_EXTRA[code] as u32) as usize;
+      assert!(distance <= out.len(), "distance reaches before the start");
+      assert!(distance <= WINDOW_SIZE, "distance outside the window");
+      // The copy may overlap what it is writing, a distance of 1 repeats one byte.
+      for _ in 0..length {
+        out.push(out[out.len() - distance]);
+      }
+    }
+  }
+
+  fn round_trip(data: &[u8]) -> Vec<u32> {
+    let (inflated, types) = inflate(&compress(data));
+    assert!(inflated == data, "round trip of {} bytes came back different", data.len());
+    types
+  }
+
+  // The same bytes every run, but without any repeats for LZ77 to find.
+  fn noise(len: usize) -> Vec<u8> {
+    let mut state = 0x2545f491u32;
+    (0..len)
+      .map(|_| {
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        state as u8
+      })
+      .collect()
+  }
+
+  // Text made of a small vocabulary, repetitive enough for matches and skewed
+  // enough that its own codes beat the fixed ones.
+  fn text(len: usize) -> Vec<u8> {
+    let words = ["the ", "server ", "sends ", "a ", "response ", "to ", "every ", "request, ", "and ", "file\n"];
+    noise(len).iter().flat_map(|&byte| words[byte as usize % words.len()].bytes()).take(len).collect()
+  }
+
+  #[test]
+  fn checksums_match_known_answers() {
+    assert_eq!(crc32(b"123456789"), 0xcbf43926);
+    assert_eq!(adler32(b"123456789"), 0x091e01de);
+    assert_eq!(crc32(b""), 0);
+    assert_eq!(adler32(b""), 1);
+  }
+
+  #[test]
+  fn adler32_sums_stay_in_range_over_long_input() {
+    // 0xff bytes make b grow fastest, the case the 5552 byte chunks guard against.
+    let data = vec![0xff; 100_000];
+    let (mut a, mut b) = (1u64, 0u64);
+    for &byte in &data {
+      a = (a + byte as u64) % 65521;
+      b = (b + a) % 65521;
+    }
+    assert_eq!(adler32(&data), (b << 16 | a) as u32);
+  }
+
+  #[test]
+  fn empty_input() {
+    assert_eq!(inflate(&compress(b"")).0, b"");
+  }
+
+  #[test]
+  fn single_byte() {
+    round_trip(b"a");
+    round_trip(&[0]);
+    round_trip(&[255]);
+  }
+
+  #[test]
+  fn short_text_uses_fixed_codes() {
+    assert_eq!(round_trip(b"<p>hello</p><p>hello</p>"), [1]);
+  }
+
+  #[test]
+  fn runs_around_max_match() {
+    for len in [MAX_MATCH - 1, MAX_MATCH, MAX_MATCH + 1, MAX_MATCH + MIN_MATCH, 2 * MAX_MATCH, 2 * MAX_MATCH + 1] {
+      round_trip(&vec![b'a'; len]);
+    }
+    // A repeated MAX_MATCH byte pattern lines up a match exactly MAX_MATCH long.
+    let pattern = noise(MAX_MATCH);
+    round_trip(&pattern.repeat(3));
+  }
+
+  #[test]
+  fn incompressible_input_is_stored() {
+    // More than one stored block can hold.
+    let data = noise(100_000);
+    let types = round_trip(&data);
+    assert!(types.iter().all(|&block_type| block_type == 0), "block types {:?}", types);
+    assert!(types.len() >= 2);
+    // Stored blocks only add their 5 byte headers.
+    assert!(compress(&data).len() <= data.len() + types.len() * 5 + 1);
+  }
+
+  #[test]
+  fn text_uses_dynamic_codes() {
+    let types = round_trip(&text(1_000_000));
+    assert!(types.contains(&2), "block types {:?}", types);
+    assert!(types.len() > 1, "expected several blocks");
+  }
+
+  #[test]
+  fn matches_reach_across_the_window() {
+    // The second copy is within the window of the first, the third only of the second.
+    let mut data = noise(20_000);
+    data.extend(noise(20_000));
+    data.extend(noise(20_000));
+    round_trip(&data);
+  }
+
+  #[test]
+  fn gzip_wraps_the_stream() {
+    let data = text(10_000);
+    let gzip = gzip(&data);
+    assert_eq!(gzip[..4], [0x1f, 0x8b, 8, 0]);
+    let trailer = &gzip[gzip.len() - 8..];
+    assert_eq!(trailer[..4], crc32(&data).to_le_bytes());
+    assert_eq!(trailer[4..], (data.len() as u32).to_le_bytes());
+    assert_eq!(inflate(&gzip[10..gzip.len() - 8]).0, data);
+  }
+
+  #[test]
+  fn zlib_wraps_the_stream() {
+    let data = text(10_000);
+    let zlib = zlib(&data);
+    assert_eq!((zlib[0] as u16 * 256 + zlib[1] as u16) % 31, 0);
+    assert_eq!(zlib[zlib.len() - 4..], adler32(&data).to_be_bytes());
+    assert_eq!(inflate(&zlib[2..zlib.len() - 4]).0, data);
+  }
+}
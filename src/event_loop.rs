@@ -0,0 +1,366 @@
+use std::collections::{HashMap, VecDeque};
+use std::fs::File;
+use std::io::{ErrorKind, Read, Result as IoResult, Write};
+use std::net::{TcpListener, TcpStream};
+use std::panic::{self, AssertUnwindSafe};
+use std::os::fd::{AsRawFd, RawFd};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use crate::http::request::PendingRequest;
+use crate::http::response::copy_file;
+use crate::http::Transmit;
+use crate::poll::{Events, Interest, Poll};
+use crate::sendfile;
+use crate::server::{next_request, Handler, Server};
+
+// How often idle connections are looked for when nothing else happens.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(1);
+
+// Instead of blocking a thread on every connection, each event loop thread registers
+// its sockets with epoll and only touches the ones that are ready to be read or written.
+// All threads wait on the same listener, epoll wakes up one of them per new connection.
+pub fn run(server: Server, listener: TcpListener, handler: impl Handler + 'static) {
+  if let Err(e) = listener.set_nonblocking(true) {
+    println!("Failed to make the listener non-blocking: {}", e);
+    return;
+  }
+
+  let server = Arc::new(server);
+  let handler = Arc::new(handler);
+
+  let threads: Vec<_> = (0..server.workers)
+    .filter_map(|_| {
+      let server = Arc::clone(&server);
+      let handler = Arc::clone(&handler);
+      let listener = match listener.try_clone() {
+        Ok(listener) => listener,
+        Err(e) => {
+          println!("Failed to share the listener: {}", e);
+          return None;
+        }
+      };
+
+      Some(thread::spawn(move || {
+        if let Err(e) = EventLoop::new(&server, listener, &*handler).and_then(|mut event_loop| event_loop.run()) {
+          println!("Event loop stopped: {}", e);
+        }
+      }))
+    })
+    .collect();
+
+  for thread in threads {
+    if thread.join().is_err() {
+      println!("Event loop thread panicked");
+    }
+  }
+}
+
+// Responses waiting for the socket to accept them, in the order they have to be sent.
+// File bodies are kept as open files and sent with sendfile once everything before
+// them is out, so a large download doesn't have to fit in memory.
+#[derive(Default)]
+struct Output {
+  chunks: VecDeque<Chunk>
+}
+
+enum Chunk {
+  // The bytes, and how many of them have been written.
+  Bytes(Vec<u8>, usize),
+  File { file: File, offset: u64, remaining: u64 }
+}
+
+impl Write for Output {
+  fn write(&mut self, buf: &[u8]) -> IoResult<usize> {
+    match self.chunks.back_mut() {
+      Some(Chunk::Bytes(bytes, _)) => bytes.extend_from_slice(buf),
+      _ => self.chunks.push_back(Chunk::Bytes(buf.to_vec(), 0))
+    }
+    Ok(buf.len())
+  }
+
+  fn flush(&mut self) -> IoResult<()> {
+    Ok(())
+  }
+}
+
+impl Transmit for Output {
+  fn send_file(&mut self, file: &mut File, offset: u64, len: u64) -> IoResult<()> {
+    // The response owns the file and is dropped before it is sent, the clone
+    // shares the same open file.
+    self.chunks.push_back(Chunk::File { file: file.try_clone()?, offset, remaining: len });
+    Ok(())
+  }
+}
+
+fn read_chunk(file: &mut File, offset: u64, len: u64) -> IoResult<Vec<u8>> {
+  let mut bytes = Vec::new();
+  copy_file(&mut bytes, file, offset, len)?;
+  Ok(bytes)
+}
+
+// A connection and the state of the request it is sending.
+struct Connection {
+  stream: TcpStream,
+  buffer: Vec<u8>,
+  pending: PendingRequest,
+  output: Output,
+  served: usize,
+  // The socket is registered for write readiness instead of read readiness.
+  waiting_to_write: bool,
+  // The client won't send anything more.
+  eof: bool,
+  // The connection is closed as soon as the output has been written.
+  closing: bool,
+  last_active: Instant,
+}
+
+struct EventLoop<'a, H: Handler> {
+  server: &'a Server,
+  handler: &'a H,
+  listener: TcpListener,
+  poll: Poll,
+  connections: HashMap<RawFd, Connection>,
+}
+
+impl<'a, H: Handler> EventLoop<'a, H> {
+  fn new(server: &'a Server, listener: TcpListener, handler: &'a H) -> std::io::Result<Self> {
+    let poll = Poll::new()?;
+    poll.add(&listener, Interest::ExclusiveRead)?;
+
+    Ok(Self { server, handler, listener, poll, connections: HashMap::new() })
+  }
+
+  fn run(&mut self) -> std::io::Result<()> {
+    let mut events = Events::with_capacity(1024);
+    let mut last_sweep = Instant::now();
+
+    loop {
+      self.poll.wait(&mut events, SWEEP_INTERVAL)?;
+
+      for event in events.iter() {
+        if event.fd() == self.listener.as_raw_fd() {
+          self.accept();
+          continue;
+        }
+
+        if event.is_readable() {
+          self.read(event.fd());
+        }
+        if event.is_writable() {
+          self.write(event.fd());
+        }
+        if event.is_closed() && !event.is_readable() {
+          self.close(event.fd());
+        }
+      }
+
+      if last_sweep.elapsed() >= SWEEP_INTERVAL {
+        self.close_idle();
+        last_sweep = Instant::now();
+      }
+    }
+  }
+
+  fn accept(&mut self) {
+    loop {
+      match self.listener.accept() {
+        Ok((stream, _)) => {
+          if let Err(e) = stream.set_nonblocking(true).and_then(|_| self.poll.add(&stream, Interest::Read)) {
+            println!("Failed to register connection: {}", e);
+            continue;
+          }
+
+          self.connections.insert(stream.as_raw_fd(), Connection {
+            stream,
+            buffer: Vec::new(),
+            pending: PendingRequest::new(),
+            output: Output::default(),
+            served: 0,
+            waiting_to_write: false,
+            eof: false,
+            closing: false,
+            last_active: Instant::now(),
+          });
+        },
+        Err(e) if e.kind() == ErrorKind::WouldBlock => return,
+        Err(e) => {
+          println!("Failed to establish connection {}", e);
+          return;
+        }
+      }
+    }
+  }
+
+  // Reads everything the socket has, then answers the complete requests in the buffer.
+  fn read(&mut self, fd: RawFd) {
+    let connection = match self.connections.get_mut(&fd) {
+      Some(connection) => connection,
+      None => return,
+    };
+    connection.last_active = Instant::now();
+
+    let mut chunk = [0; 4096];
+    loop {
+      match connection.stream.read(&mut chunk) {
+        Ok(0) => {
+          connection.eof = true;
+          break;
+        },
+        Ok(n) => connection.buffer.extend_from_slice(&chunk[..n]),
+        Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+        Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+        Err(e) => {
+          println!("Failed to read from connection: {}", e);
+          self.close(fd);
+          return;
+        }
+      }
+    }
+
+    self.process(fd);
+  }
+
+  // Turns every complete request in the buffer into a response, in the order they
+  // were sent, and starts writing them out.
+  fn process(&mut self, fd: RawFd) {
+    let connection = match self.connections.get_mut(&fd) {
+      Some(connection) => connection,
+      None => return,
+    };
+
+    let (server, handler) = (self.server, self.handler);
+    while let Some(parsed) = next_request(&connection.buffer, &mut connection.pending) {
+      connection.served += 1;
+
+      // A panic would otherwise unwind out of the event loop and take every
+      // connection of this thread with it, like a panicking job in the thread pool.
+      let answered = panic::catch_unwind(AssertUnwindSafe(|| {
+        let (mut response, length) = server.respond(&connection.buffer, parsed, connection.served, handler);
+        (response.send(&mut connection.output), response.keep_alive(), length)
+      }));
+
+      let (sent, keep_alive, length) = match answered {
+        Ok(answered) => answered,
+        Err(_) => {
+          println!("Recovered from a panic while answering a request, closing the connection");
+          self.close(fd);
+          return;
+        }
+      };
+
+      if let Err(e) = sent {
+        println!("Failed to send response: {}", e);
+        connection.closing = true;
+        break;
+      }
+
+      connection.buffer.drain(..length);
+      connection.pending.reset();
+
+      if !keep_alive {
+        connection.closing = true;
+        break;
+      }
+    }
+
+    // Whatever is left in the buffer is an incomplete request that will never be finished.
+    if connection.eof {
+      connection.closing = true;
+    }
+
+    self.write(fd);
+  }
+
+  fn write(&mut self, fd: RawFd) {
+    let connection = match self.connections.get_mut(&fd) {
+      Some(connection) => connection,
+      None => return,
+    };
+
+    while let Some(chunk) = connection.output.chunks.front_mut() {
+      let result = match chunk {
+        Chunk::Bytes(bytes, written) => connection.stream.write(&bytes[*written..]).map(|n| {
+          *written += n;
+          *written == bytes.len()
+        }),
+        Chunk::File { file, offset, remaining } => match sendfile::send(&connection.stream, file, offset, *remaining) {
+          // The file got shorter since its Content-Length was sent.
+          Ok(0) => Err(ErrorKind::UnexpectedEof.into()),
+          Ok(n) => {
+            *remaining -= n as u64;
+            Ok(*remaining == 0)
+          },
+          // A file sendfile can't send is read into memory and written from there.
+          Err(e) if sendfile::is_unsupported(&e) => read_chunk(file, *offset, *remaining).map(|bytes| {
+            *chunk = Chunk::Bytes(bytes, 0);
+            false
+          }),
+          Err(e) => Err(e)
+        }
+      };
+
+      match result {
+        Ok(done) => {
+          if done {
+            connection.output.chunks.pop_front();
+          }
+          connection.last_active = Instant::now();
+        },
+        Err(e) if e.kind() == ErrorKind::WouldBlock => {
+          // Stop reading until the client has taken the responses it asked for.
+          if !connection.waiting_to_write {
+            connection.waiting_to_write = true;
+            if let Err(e) = self.poll.modify(&connection.stream, Interest::Write) {
+              println!("Failed to wait for connection: {}", e);
+              self.close(fd);
+            }
+          }
+          return;
+        },
+        Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+        Err(e) => {
+          println!("Failed to send response: {}", e);
+          self.close(fd);
+          return;
+        }
+      }
+    }
+
+    if connection.closing {
+      self.close(fd);
+      return;
+    }
+
+    if connection.waiting_to_write {
+      connection.waiting_to_write = false;
+      if let Err(e) = self.poll.modify(&connection.stream, Interest::Read) {
+        println!("Failed to wait for connection: {}", e);
+        self.close(fd);
+        return;
+      }
+      // Pipelined requests may have arrived while we were waiting to write.
+      self.process(fd);
+    }
+  }
+
+  fn close_idle(&mut self) {
+    let idle_timeout = self.server.idle_timeout;
+    let idle: Vec<RawFd> = self.connections
+      .iter()
+      .filter(|(_, connection)| connection.last_active.elapsed() >= idle_timeout)
+      .map(|(fd, _)| *fd)
+      .collect();
+
+    for fd in idle {
+      self.close(fd);
+    }
+  }
+
+  // Dropping the stream closes the socket, which also removes it from epoll.
+  fn close(&mut self, fd: RawFd) {
+    if let Some(connection) = self.connections.remove(&fd) {
+      let _ = self.poll.delete(&connection.stream);
+    }
+  }
+}
@@ -0,0 +1,238 @@
+use super::{Headers, ParseError};
+use std::convert::TryFrom;
+use std::io::{Write, Result as IoResult};
+use std::str;
+
+// Transfer-Encoding: chunked sends the body as a list of chunks, each one
+// prefixed by its size in hex, and ends with a zero sized chunk followed by
+// optional trailer headers:
+//
+// 4;name=value\r\nWiki\r\n5\r\npedia\r\n0\r\nExpires: never\r\n\r\n
+
+// The longest chunk size line, extensions included, we wait for. Nothing needs
+// more, and a client sending an endless one would have us search it over and over.
+const MAX_LINE: usize = 4096;
+
+// The decoded body and the trailers of a chunked message.
+pub struct Decoded<'buf> {
+  pub body: Vec<u8>,
+  pub trailers: Headers<'buf>,
+}
+
+// How far length() got through a chunked body, so the next call goes on from
+// there instead of going over the chunks that were read already.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Progress {
+  // The start of the first chunk that hasn't been read completely, or of the
+  // trailers after the last chunk.
+  pos: usize,
+  // Once the last chunk is read, how much of the trailers has been searched for their end.
+  trailers_scanned: Option<usize>,
+}
+
+// Returns the length of the chunked body at the start of buf, trailers included,
+// or None if the last chunk or the trailers haven't been read yet.
+// A chunk that would take the body past max_len is refused before it arrives.
+// buf may only grow between calls with the same progress.
+pub fn length(buf: &[u8], max_len: usize, progress: &mut Progress) -> Result<Option<usize>, ParseError> {
+  Ok(walk(buf, max_len, progress, |_| {})?.map(|(_, end)| end))
+}
+
+pub fn decode(buf: &[u8]) -> Result<Decoded<'_>, ParseError> {
+  let mut body = Vec::new();
+  let (trailer_block, _) = walk(buf, usize::MAX, &mut Progress::default(), |data| body.extend_from_slice(data))?
+    .ok_or(ParseError::IncompleteBody)?;
+
+  let trailer_block = str::from_utf8(trailer_block)?;
+  let trailers = Headers::try_from(trailer_block)?;
+
+  Ok(Decoded { body, trailers })
+}
+
+// Goes through the chunks from where progress left off and hands the data of
+// each one to on_data. Returns the trailer block (without the final "\r\n") and
+// the end of the message.
+fn walk<'buf>(
+  buf: &'buf [u8],
+  max_len: usize,
+  progress: &mut Progress,
+  mut on_data: impl FnMut(&'buf [u8])
+) -> Result<Option<(&'buf [u8], usize)>, ParseError> {
+  while progress.trailers_scanned.is_none() {
+    let pos = progress.pos;
+    let line = &buf[pos..buf.len().min(pos + MAX_LINE + 2)];
+    let line_end = match find(line, b"\r\n") {
+      Some(i) => pos + i,
+      None if line.len() == MAX_LINE + 2 => return Err(ParseError::MalformedChunk),
+      None => return Ok(None),
+    };
+    let size = chunk_size(&buf[pos..line_end])?;
+    let data_start = line_end + 2;
+
+    if size == 0 {
+      progress.pos = data_start;
+      progress.trailers_scanned = Some(data_start);
+      break;
+    }
+
+    // The size comes from the client, a huge one must neither overflow nor
+    // have us wait for data we would refuse anyway.
+    let data_end = data_start.checked_add(size).ok_or(ParseError::InvalidChunkSize)?;
+    let chunk_end = data_end.checked_add(2).ok_or(ParseError::InvalidChunkSize)?;
+    if chunk_end > max_len {
+      return Err(ParseError::RequestTooLarge);
+    }
+
+    if buf.len() < chunk_end {
+      return Ok(None);
+    }
+    if &buf[data_end..chunk_end] != b"\r\n" {
+      return Err(ParseError::MalformedChunk);
+    }
+
+    on_data(&buf[data_start..data_end]);
+    progress.pos = chunk_end;
+  }
+
+  // No trailers, the last chunk is directly followed by the empty line.
+  let pos = progress.pos;
+  if buf.len() < pos + 2 {
+    return Ok(None);
+  }
+  if &buf[pos..pos + 2] == b"\r\n" {
+    return Ok(Some((&[], pos + 2)));
+  }
+
+  // The "\r\n\r\n" may have been split between two reads.
+  let from = progress.trailers_scanned.unwrap_or(pos).saturating_sub(3).max(pos);
+  match find(&buf[from..], b"\r\n\r\n") {
+    Some(i) => Ok(Some((&buf[pos..from + i], from + i + 4))),
+    None => {
+      progress.trailers_scanned = Some(buf.len());
+      Ok(None)
+    },
+  }
+}
+
+// Parses the chunk size line, ignoring any chunk extensions after ';'.
+fn chunk_size(line: &[u8]) -> Result<usize, ParseError> {
+  let line = str::from_utf8(line).map_err(|_| ParseError::InvalidChunkSize)?;
+  let (size, extensions) = line.split_once(';').unwrap_or((line, ""));
+  let size = size.trim_end_matches([' ', '\t']);
+
+  if extensions.bytes().any(|b| b < 0x20 && b != b'\t') {
+    return Err(ParseError::MalformedChunk);
+  }
+
+  // from_str_radix accepts a leading '+', which is not valid here.
+  if size.is_empty() || !size.bytes().all(|b| b.is_ascii_hexdigit()) {
+    return Err(ParseError::InvalidChunkSize);
+  }
+
+  usize::from_str_radix(size, 16).map_err(|_| ParseError::InvalidChunkSize)
+}
+
+fn find(buf: &[u8], needle: &[u8]) -> Option<usize> {
+  buf.windows(needle.len()).position(|window| window == needle)
+}
+
+// Wraps a stream and writes everything written to it as chunks.
+// finish() has to be called to write the last chunk.
+pub struct ChunkedWriter<'a> {
+  stream: &'a mut dyn Write
+}
+
+impl<'a> ChunkedWriter<'a> {
+  pub fn new(stream: &'a mut dyn Write) -> Self {
+    Self { stream }
+  }
+
+  pub fn finish(self) -> IoResult<()> {
+    self.stream.write_all(b"0\r\n\r\n")?;
+    self.stream.flush()
+  }
+}
+
+impl Write for ChunkedWriter<'_> {
+  fn write(&mut self, buf: &[u8]) -> IoResult<usize> {
+    // A zero sized chunk would end the body.
+    if buf.is_empty() {
+      return Ok(0);
+    }
+
+    write!(self.stream, "{:X}\r\n", buf.len())?;
+    self.stream.write_all(buf)?;
+    self.stream.write_all(b"\r\n")?;
+    Ok(buf.len())
+  }
+
+  fn flush(&mut self) -> IoResult<()> {
+    self.stream.flush()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const MESSAGE: &[u8] = b"4;name=value\r\nWiki\r\n5\r\npedia\r\n0\r\nExpires: never\r\n\r\n";
+
+  // Hands the message to length() a byte at a time, the way it may arrive.
+  fn length_by_byte(message: &[u8]) -> Result<Option<usize>, ParseError> {
+    let mut progress = Progress::default();
+    for end in 1..message.len() {
+      if let Some(length) = length(&message[..end], usize::MAX, &mut progress)? {
+        return Ok(Some(length));
+      }
+    }
+    length(message, usize::MAX, &mut progress)
+  }
+
+  #[test]
+  fn decodes_chunks_and_trailers() {
+    let decoded = decode(MESSAGE).unwrap();
+    assert_eq!(decoded.body, b"Wikipedia");
+    assert_eq!(decoded.trailers.get("Expires"), Some("never"));
+  }
+
+  #[test]
+  fn finds_the_end_of_the_message() {
+    let mut message = MESSAGE.to_vec();
+    message.extend_from_slice(b"GET / HTTP/1.1\r\n");
+    assert_eq!(length(&message, usize::MAX, &mut Progress::default()).unwrap(), Some(MESSAGE.len()));
+    assert_eq!(length(b"1\r\na\r\n0\r\n\r\n", usize::MAX, &mut Progress::default()).unwrap(), Some(11));
+  }
+
+  #[test]
+  fn resumes_where_it_left_off() {
+    assert_eq!(length_by_byte(MESSAGE).unwrap(), Some(MESSAGE.len()));
+    assert_eq!(length_by_byte(b"1\r\na\r\n0\r\n\r\n").unwrap(), Some(11));
+  }
+
+  #[test]
+  fn waits_for_the_rest() {
+    for end in 0..MESSAGE.len() {
+      assert_eq!(length(&MESSAGE[..end], usize::MAX, &mut Progress::default()).unwrap(), None, "{} bytes", end);
+    }
+  }
+
+  #[test]
+  fn caps_the_chunk_size_line() {
+    let mut line = b"1;".to_vec();
+    line.resize(MAX_LINE, b'a');
+    line.extend_from_slice(b"\r\na\r\n0\r\n\r\n");
+    assert_eq!(length(&line, usize::MAX, &mut Progress::default()).unwrap(), Some(line.len()));
+
+    let mut line = b"1;".to_vec();
+    line.resize(MAX_LINE + 2, b'a');
+    assert!(matches!(length(&line, usize::MAX, &mut Progress::default()), Err(ParseError::MalformedChunk)));
+  }
+
+  #[test]
+  fn refuses_invalid_chunks() {
+    for message in [&b"x\r\n"[..], b"+1\r\na\r\n", b"ffffffffffffffffff\r\n", b"1\r\nab\r\n"] {
+      assert!(length(message, usize::MAX, &mut Progress::default()).is_err(), "{:?}", message);
+    }
+    assert!(matches!(length(b"10\r\n", 8, &mut Progress::default()), Err(ParseError::RequestTooLarge)));
+  }
+}
@@ -0,0 +1,95 @@
+use super::{date, Method, Request};
+use std::fs::Metadata;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// What a client can compare its cached copy with to find out whether it changed.
+pub struct Validators {
+  pub etag: String,
+  pub last_modified: SystemTime,
+}
+
+// The outcome of the conditional request headers.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Precondition {
+  // Answer the request as usual.
+  Proceed,
+  // 304, the client's copy is still fresh.
+  NotModified,
+  // 412, the request only applies to another version of the resource.
+  Failed,
+}
+
+impl Validators {
+  // The ETag is built from the inode, the size and the modification time of the file,
+  // so it changes whenever the file does without having to read its contents.
+  pub fn from_metadata(metadata: &Metadata) -> Self {
+    let last_modified = metadata.modified().unwrap_or(UNIX_EPOCH);
+    let mtime = last_modified.duration_since(UNIX_EPOCH).map(|d| d.as_nanos()).unwrap_or(0);
+
+    Self {
+      etag: format!("\"{:x}-{:x}-{:x}\"", inode(metadata), metadata.len(), mtime),
+      // HTTP dates only have whole seconds.
+      last_modified: UNIX_EPOCH + Duration::from_secs((mtime / 1_000_000_000) as u64),
+    }
+  }
+
+  // Evaluates the conditional headers in the order RFC 9110 section 13.2.2 asks for:
+  // If-Match, then If-Unmodified-Since if there was no If-Match, then If-None-Match,
+  // then If-Modified-Since if there was no If-None-Match. Dates that don't parse
+  // make their header count as missing.
+  pub fn evaluate(&self, request: &Request) -> Precondition {
+    let headers = request.headers();
+
+    if headers.contains("If-Match") {
+      if !headers.get_all("If-Match").any(|value| matches_etag(value, &self.etag, false)) {
+        return Precondition::Failed;
+      }
+    } else if let Some(since) = headers.get("If-Unmodified-Since").and_then(date::parse) {
+      if self.last_modified > since {
+        return Precondition::Failed;
+      }
+    }
+
+    let is_read = matches!(request.method(), Method::GET | Method::HEAD);
+
+    if headers.contains("If-None-Match") {
+      if headers.get_all("If-None-Match").any(|value| matches_etag(value, &self.etag, true)) {
+        return if is_read { Precondition::NotModified } else { Precondition::Failed };
+      }
+    } else if is_read {
+      if let Some(since) = headers.get("If-Modified-Since").and_then(date::parse) {
+        if self.last_modified <= since {
+          return Precondition::NotModified;
+        }
+      }
+    }
+
+    Precondition::Proceed
+  }
+}
+
+// Checks a list of entity tags like "\"a\", W/\"b\"" or "*" against our ETag.
+// The weak comparison ignores the W/ prefix, the strong one never matches weak tags.
+pub fn matches_etag(list: &str, etag: &str, weak: bool) -> bool {
+  list.split(',').map(str::trim).any(|tag| {
+    if tag == "*" {
+      return true;
+    }
+
+    match tag.strip_prefix("W/") {
+      Some(tag) => weak && tag == etag.trim_start_matches("W/"),
+      None => tag == etag || (weak && tag == etag.trim_start_matches("W/")),
+    }
+  })
+}
+
+#[cfg(unix)]
+fn inode(metadata: &Metadata) -> u64 {
+  use std::os::unix::fs::MetadataExt;
+  metadata.ino()
+}
+
+#[cfg(not(unix))]
+fn inode(_: &Metadata) -> u64 {
+  0
+}
@@ -0,0 +1,170 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const DAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+// Formats the time the way HTTP headers like Date and Last-Modified expect it:
+// Sun, 06 Nov 1994 08:49:37 GMT
+pub fn format(time: SystemTime) -> String {
+  let secs = time.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+  let days = secs / 86400;
+  let secs_of_day = secs % 86400;
+
+  let (year, month, day) = civil_from_days(days as i64);
+
+  format!(
+    "{}, {:02} {} {} {:02}:{:02}:{:02} GMT",
+    // 1970-01-01 was a Thursday.
+    DAYS[(days % 7) as usize],
+    day,
+    MONTHS[month as usize - 1],
+    year,
+    secs_of_day / 3600,
+    secs_of_day % 3600 / 60,
+    secs_of_day % 60
+  )
+}
+
+// Parses an HTTP date. Besides the format above, senders are allowed to use two
+// obsolete ones that we have to understand as well:
+// Sunday, 06-Nov-94 08:49:37 GMT  (RFC 850)
+// Sun Nov  6 08:49:37 1994        (asctime)
+pub fn parse(s: &str) -> Option<SystemTime> {
+  let parts: Vec<&str> = s.split_whitespace().collect();
+
+  let (day, month, year, time) = match parts.as_slice() {
+    [_, day, month, year, time, "GMT"] => (*day, *month, *year, *time),
+    [_, date, time, "GMT"] => {
+      let mut date = date.split('-');
+      let (day, month, year) = (date.next()?, date.next()?, date.next()?);
+      (day, month, year, *time)
+    },
+    [_, month, day, time, year] => (*day, *month, *year, *time),
+    _ => return None
+  };
+
+  let day: u32 = day.parse().ok()?;
+  let month = MONTHS.iter().position(|m| *m == month)? as u32 + 1;
+  let mut year: i64 = year.parse().ok()?;
+  // RFC 850 dates only have two digits for the year, they are read as 1970 to 2069.
+  if year < 100 {
+    year += if year < 70 { 2000 } else { 1900 };
+  }
+  // Four digit years are all a header can hold. Anything further off is made up,
+  // and would overflow the arithmetic below.
+  if !(1..=9999).contains(&year) {
+    return None;
+  }
+
+  let mut time = time.split(':').map(|part| part.parse::<u64>().ok());
+  let (hour, minute, second) = (time.next()??, time.next()??, time.next()??);
+  if day == 0 || day > 31 || hour > 23 || minute > 59 || second > 60 {
+    return None;
+  }
+
+  let days = u64::try_from(days_from_civil(year, month, day)).ok()?;
+  let secs = days.checked_mul(86400)?.checked_add(hour * 3600 + minute * 60 + second)?;
+  UNIX_EPOCH.checked_add(Duration::from_secs(secs))
+}
+
+// Turns a (year, month, day) date into days since 1970-01-01, the inverse of civil_from_days.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+  let year = if month <= 2 { year - 1 } else { year };
+  let era = year.div_euclid(400);
+  let year_of_era = year.rem_euclid(400);
+  let mp = if month > 2 { month - 3 } else { month + 9 } as i64;
+  let day_of_year = (153 * mp + 2) / 5 + day as i64 - 1;
+  let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+  era * 146097 + day_of_era - 719468
+}
+
+// Turns days since 1970-01-01 into a (year, month, day) date.
+// The algorithm is Howard Hinnant's, it shifts the year to start in March so the
+// leap day is the last day of the year.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+  let z = days + 719468;
+  let era = z.div_euclid(146097);
+  let day_of_era = z.rem_euclid(146097);
+  let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+  let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+  let mp = (5 * day_of_year + 2) / 153;
+  let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+  let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+  let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+  (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  // Sun, 06 Nov 1994 08:49:37 GMT, the example date in RFC 9110.
+  fn example() -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(784111777)
+  }
+
+  #[test]
+  fn formats_imf_fixdate() {
+    assert_eq!(format(example()), "Sun, 06 Nov 1994 08:49:37 GMT");
+    assert_eq!(format(UNIX_EPOCH), "Thu, 01 Jan 1970 00:00:00 GMT");
+    // 2024-02-29 23:59:59, a leap day.
+    assert_eq!(format(UNIX_EPOCH + Duration::from_secs(1709251199)), "Thu, 29 Feb 2024 23:59:59 GMT");
+  }
+
+  #[test]
+  fn parses_every_date_format() {
+    assert_eq!(parse("Sun, 06 Nov 1994 08:49:37 GMT"), Some(example()));
+    assert_eq!(parse("Sunday, 06-Nov-94 08:49:37 GMT"), Some(example()));
+    assert_eq!(parse("Sun Nov  6 08:49:37 1994"), Some(example()));
+  }
+
+  #[test]
+  fn reads_two_digit_years_as_1970_to_2069() {
+    assert_eq!(parse("Thursday, 01-Jan-70 00:00:00 GMT"), Some(UNIX_EPOCH));
+    assert_eq!(parse("Tuesday, 01-Jan-69 00:00:00 GMT").map(format), Some("Tue, 01 Jan 2069 00:00:00 GMT".to_string()));
+  }
+
+  #[test]
+  fn parses_what_it_formats() {
+    for secs in [0, 784111777, 951782400, 1709251199, 253402300799] {
+      let time = UNIX_EPOCH + Duration::from_secs(secs);
+      assert_eq!(parse(&format(time)), Some(time));
+    }
+  }
+
+  #[test]
+  fn rejects_invalid_dates() {
+    for date in [
+      "",
+      "Sun, 06 Nov 1994 08:49:37",
+      "Sun, 06 Nov 1994 08:49:37 UTC",
+      "Sun, 06 Foo 1994 08:49:37 GMT",
+      "Sun, 00 Nov 1994 08:49:37 GMT",
+      "Sun, 32 Nov 1994 08:49:37 GMT",
+      "Sun, 06 Nov 1994 24:00:00 GMT",
+      "Sun, 06 Nov 1994 08:60:00 GMT",
+      "Sun, 06 Nov 1994 08:49 GMT",
+      "Sun, 06 Nov 1994 08:49:x GMT",
+      // Before 1970 can't be a SystemTime after UNIX_EPOCH.
+      "Mon, 01 Jan 1900 00:00:00 GMT"
+    ] {
+      assert_eq!(parse(date), None, "{:?}", date);
+    }
+  }
+
+  #[test]
+  fn rejects_far_off_years() {
+    for date in [
+      "Sun, 06 Nov 10000 08:49:37 GMT",
+      "Sun, 06 Nov 400000000000 08:49:37 GMT",
+      "Sun, 06 Nov 9999999999999999 08:49:37 GMT",
+      "Sun, 06 Nov -1994 08:49:37 GMT",
+      "Sunday, 06-Nov-400000000000 08:49:37 GMT",
+      "Sun Nov  6 08:49:37 9223372036854775807"
+    ] {
+      assert_eq!(parse(date), None, "{:?}", date);
+    }
+  }
+}
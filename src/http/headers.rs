@@ -0,0 +1,92 @@
+use super::ParseError;
+use std::convert::TryFrom;
+
+// Header names are case-insensitive and the same header may appear more than once,
+// so instead of a HashMap we keep the (name, value) pairs in the order they arrived
+// and compare names with eq_ignore_ascii_case on lookup.
+#[derive(Clone, Debug, Default)]
+pub struct Headers<'buf> {
+  entries: Vec<(&'buf str, &'buf str)>
+}
+
+impl<'buf> Headers<'buf> {
+  // Returns the first value for the header.
+  pub fn get(&self, name: &str) -> Option<&'buf str> {
+    self.get_all(name).next()
+  }
+
+  // Returns every value of the header, in the order they were sent.
+  pub fn get_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'buf str> + 'a {
+    self.entries
+      .iter()
+      .filter(move |(key, _)| key.eq_ignore_ascii_case(name))
+      .map(|(_, value)| *value)
+  }
+
+  // Checks comma separated headers like "Connection: keep-alive, Upgrade" for a token.
+  pub fn has_token(&self, name: &str, token: &str) -> bool {
+    self.get_all(name)
+      .flat_map(|value| value.split(','))
+      .any(|value| value.trim().eq_ignore_ascii_case(token))
+  }
+
+  pub fn contains(&self, name: &str) -> bool {
+    self.get(name).is_some()
+  }
+
+  pub fn iter(&self) -> impl Iterator<Item = (&'buf str, &'buf str)> + '_ {
+    self.entries.iter().copied()
+  }
+
+  pub fn len(&self) -> usize {
+    self.entries.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.entries.is_empty()
+  }
+}
+
+// Parses the header block, that is everything between the request line
+// and the empty line, without the final "\r\n".
+//
+// Host: localhost:8080\r\nAccept: text/html\r\nAccept: text/css
+impl<'buf> TryFrom<&'buf str> for Headers<'buf> {
+  type Error = ParseError;
+
+  fn try_from(block: &'buf str) -> Result<Self, Self::Error> {
+    let mut entries = Vec::new();
+
+    if block.is_empty() {
+      return Ok(Headers { entries });
+    }
+
+    for line in block.split("\r\n") {
+      // Lines starting with whitespace are the obsolete "line folding",
+      // RFC 9112 allows servers to reject them.
+      if line.starts_with(' ') || line.starts_with('\t') {
+        return Err(ParseError::MalformedHeader);
+      }
+
+      let (name, value) = line.split_once(':').ok_or(ParseError::MalformedHeader)?;
+
+      if name.is_empty() || !name.bytes().all(is_token_char) {
+        return Err(ParseError::InvalidHeaderName);
+      }
+
+      let value = value.trim_matches(|c| c == ' ' || c == '\t');
+      if value.bytes().any(|b| (b < 0x20 && b != b'\t') || b == 0x7f) {
+        return Err(ParseError::InvalidHeaderValue);
+      }
+
+      entries.push((name, value));
+    }
+
+    Ok(Headers { entries })
+  }
+}
+
+// tchar from RFC 9110, the characters allowed in a header name.
+fn is_token_char(b: u8) -> bool {
+  b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b)
+}
@@ -1,6 +1,7 @@
+use std::fmt::{Display, Formatter, Result as FmtResult};
 use std::str::FromStr;
 
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 
 pub enum Method {
   GET,
@@ -33,4 +34,26 @@ impl FromStr for Method {
   }
 }
 
+impl Method {
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      Self::GET => "GET",
+      Self::POST => "POST",
+      Self::PUT => "PUT",
+      Self::DELETE => "DELETE",
+      Self::HEAD => "HEAD",
+      Self::CONNECT => "CONNECT",
+      Self::OPTIONS => "OPTIONS",
+      Self::TRACE => "TRACE",
+      Self::PATCH => "PATCH"
+    }
+  }
+}
+
+impl Display for Method {
+  fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+    write!(f, "{}", self.as_str())
+  }
+}
+
 pub struct MethodError;
\ No newline at end of file
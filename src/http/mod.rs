@@ -1,12 +1,22 @@
 pub mod request;
+pub mod headers;
+pub mod chunked;
+pub mod date;
+pub mod conditional;
+pub mod range;
+pub mod negotiate;
 pub mod method;
 pub mod query_string;
+pub mod percent;
+pub mod path;
 pub mod response;
 pub mod status_code;
 
 pub use request::Request;
 pub use method::Method;
+pub use headers::Headers;
 pub use request::ParseError;
+#[allow(unused_imports)]
 pub use query_string::{QueryString, Value as QueryStringValue};
-pub use response::Response;
+pub use response::{Part, Response, Transmit};
 pub use status_code::StatusCode;
\ No newline at end of file
@@ -0,0 +1,90 @@
+// Content negotiation, picking what to send from a header like
+// Accept: text/html, application/json;q=0.9, */*;q=0.1
+
+// Returns the offer the client prefers, or None if it accepts none of them.
+// Offers the client ranks the same keep the order they were given in.
+pub fn preferred_media_type<'a>(accept: Option<&str>, offers: &[&'a str]) -> Option<&'a str> {
+  // No Accept header means anything goes.
+  let accept = match accept {
+    Some(accept) => accept,
+    None => return offers.first().copied()
+  };
+
+  let ranges: Vec<(&str, f32)> = parse(accept).collect();
+
+  let mut best: Option<(&str, f32)> = None;
+  for offer in offers {
+    // The most specific matching range decides the quality of the offer.
+    let quality = ranges
+      .iter()
+      .filter(|(range, _)| media_range_matches(range, offer))
+      .max_by_key(|(range, _)| specificity(range))
+      .map(|(_, quality)| *quality)
+      .unwrap_or(0.0);
+
+    if quality > 0.0 && best.is_none_or(|(_, best_quality)| quality > best_quality) {
+      best = Some((offer, quality));
+    }
+  }
+
+  best.map(|(offer, _)| offer)
+}
+
+// Picks the content coding to send from an Accept-Encoding header like
+// Accept-Encoding: gzip, deflate;q=0.5, *;q=0
+// Returns None when the client would rather have the response as it is.
+// Codings the client ranks the same keep the order they were offered in.
+pub fn preferred_encoding<'a>(accept_encoding: &str, offers: &[&'a str]) -> Option<&'a str> {
+  let codings: Vec<(&str, f32)> = parse(accept_encoding).collect();
+  let quality = |coding: &str| {
+    codings.iter().find(|(name, _)| name.eq_ignore_ascii_case(coding))
+      .or_else(|| codings.iter().find(|(name, _)| *name == "*"))
+      .map(|(_, quality)| *quality)
+  };
+
+  let mut best: Option<(&str, f32)> = None;
+  for offer in offers {
+    let quality = quality(offer).unwrap_or(0.0);
+    if quality > 0.0 && best.is_none_or(|(_, best_quality)| quality > best_quality) {
+      best = Some((offer, quality));
+    }
+  }
+
+  // The uncompressed response is always acceptable, unless the client says otherwise.
+  let identity = quality("identity").unwrap_or(1.0);
+  best.filter(|(_, quality)| *quality >= identity).map(|(offer, _)| offer)
+}
+
+// Splits a header into its values and their q parameter, which defaults to 1.
+pub fn parse(header: &str) -> impl Iterator<Item = (&str, f32)> {
+  header.split(',').filter_map(|item| {
+    let mut params = item.split(';').map(str::trim);
+    let value = params.next().filter(|value| !value.is_empty())?;
+
+    let quality = params
+      .filter_map(|param| param.split_once('='))
+      .find(|(name, _)| name.trim().eq_ignore_ascii_case("q"))
+      .and_then(|(_, q)| q.trim().parse::<f32>().ok())
+      .map(|q| q.clamp(0.0, 1.0))
+      .unwrap_or(1.0);
+
+    Some((value, quality))
+  })
+}
+
+fn media_range_matches(range: &str, media_type: &str) -> bool {
+  let (range_type, range_subtype) = range.split_once('/').unwrap_or((range, ""));
+  let (offer_type, offer_subtype) = media_type.split_once('/').unwrap_or((media_type, ""));
+
+  (range_type == "*" || range_type.eq_ignore_ascii_case(offer_type))
+    && (range_subtype == "*" || range_subtype.eq_ignore_ascii_case(offer_subtype))
+}
+
+// text/html beats text/* which beats */*.
+fn specificity(range: &str) -> u8 {
+  match range {
+    "*/*" => 0,
+    range if range.ends_with("/*") => 1,
+    _ => 2
+  }
+}
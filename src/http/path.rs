@@ -0,0 +1,125 @@
+use super::{percent, ParseError};
+use std::borrow::Cow;
+use std::str;
+
+// Turns the raw path of the request target into the path handlers work with:
+// percent-decoded, with "." and ".." segments resolved and empty segments removed.
+//
+// /a/../about%2Ehtml  ->  /about.html
+// //docs/./guide/     ->  /docs/guide/
+//
+// ".." can never climb above the root. Paths that decode to a NUL byte or to
+// invalid UTF-8 are rejected, they only show up in attacks. The path is borrowed
+// from the buffer when it already was in its normal form.
+pub fn normalize(raw: &str) -> Result<Cow<'_, str>, ParseError> {
+  // The asterisk-form, "OPTIONS * HTTP/1.1" asks about the server as a whole.
+  if raw == "*" {
+    return Ok(Cow::Borrowed(raw));
+  }
+
+  if !raw.starts_with('/') {
+    return Err(ParseError::InvalidPath);
+  }
+
+  let decoded = match percent::decode(raw, false) {
+    Cow::Borrowed(_) => Cow::Borrowed(raw),
+    Cow::Owned(bytes) => Cow::Owned(String::from_utf8(bytes).map_err(|_| ParseError::InvalidPath)?)
+  };
+  if decoded.contains('\0') {
+    return Err(ParseError::InvalidPath);
+  }
+
+  let mut segments: Vec<&str> = Vec::new();
+  let mut trailing_slash = false;
+
+  for segment in decoded.split('/').skip(1) {
+    trailing_slash = matches!(segment, "" | "." | "..");
+    match segment {
+      "" | "." => {},
+      ".." => {
+        segments.pop();
+      },
+      segment => segments.push(segment)
+    }
+  }
+
+  let mut normalized = String::with_capacity(decoded.len());
+  for segment in &segments {
+    normalized.push('/');
+    normalized.push_str(segment);
+  }
+  if trailing_slash || segments.is_empty() {
+    normalized.push('/');
+  }
+
+  if normalized == decoded {
+    Ok(decoded)
+  } else {
+    Ok(Cow::Owned(normalized))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn normalized(raw: &str) -> String {
+    normalize(raw).unwrap().into_owned()
+  }
+
+  #[test]
+  fn resolves_dot_segments() {
+    assert_eq!(normalized("/a/../about%2Ehtml"), "/about.html");
+    assert_eq!(normalized("//docs/./guide/"), "/docs/guide/");
+    assert_eq!(normalized("/docs/guide/.."), "/docs/");
+    assert_eq!(normalized("/"), "/");
+  }
+
+  #[test]
+  fn never_climbs_above_the_root() {
+    assert_eq!(normalized("/../../etc/passwd"), "/etc/passwd");
+    assert_eq!(normalized("/a/../../etc/passwd"), "/etc/passwd");
+    assert_eq!(normalized("/.."), "/");
+  }
+
+  #[test]
+  fn decodes_before_resolving() {
+    assert_eq!(normalized("/%2e%2e/%2e%2e/etc/passwd"), "/etc/passwd");
+    assert_eq!(normalized("/%2E%2E/etc/passwd"), "/etc/passwd");
+    assert_eq!(normalized("/..%2f..%2fetc%2fpasswd"), "/etc/passwd");
+    assert_eq!(normalized("/a/%2e%2e"), "/");
+  }
+
+  #[test]
+  fn keeps_backslashes() {
+    // A backslash is an ordinary character in a URL, StaticFiles refuses it
+    // since it is a separator on Windows.
+    assert_eq!(normalized("/..\\..\\etc\\passwd"), "/..\\..\\etc\\passwd");
+    assert_eq!(normalized("/%5c..%5cetc"), "/\\..\\etc");
+  }
+
+  #[test]
+  fn rejects_nul_bytes_and_invalid_utf8() {
+    assert!(normalize("/index.html%00.txt").is_err());
+    assert!(normalize("/%00").is_err());
+    assert!(normalize("/%ff").is_err());
+  }
+
+  #[test]
+  fn rejects_paths_without_a_leading_slash() {
+    assert!(normalize("index.html").is_err());
+    assert!(normalize("../etc/passwd").is_err());
+    assert!(normalize("").is_err());
+  }
+
+  #[test]
+  fn accepts_the_asterisk_form() {
+    assert_eq!(normalized("*"), "*");
+  }
+
+  #[test]
+  fn borrows_paths_already_normal() {
+    assert!(matches!(normalize("/css/style.css"), Ok(Cow::Borrowed(_))));
+    assert!(matches!(normalize("/css/./style.css"), Ok(Cow::Owned(_))));
+  }
+}
@@ -0,0 +1,92 @@
+use std::borrow::Cow;
+
+// Percent-encoding puts bytes that can't appear in a URL as "%" and two hex digits,
+// "John Doe" becomes "John%20Doe". In form data a space can also be written as "+".
+
+// Decodes the percent-encoded bytes. A "%" that isn't followed by two hex digits is
+// kept as it is, like browsers do. Nothing is copied when there is nothing to decode.
+pub fn decode(s: &str, plus_as_space: bool) -> Cow<'_, [u8]> {
+  let needs_decoding = s.bytes().any(|b| b == b'%' || (plus_as_space && b == b'+'));
+  if !needs_decoding {
+    return Cow::Borrowed(s.as_bytes());
+  }
+
+  let bytes = s.as_bytes();
+  let mut decoded = Vec::with_capacity(bytes.len());
+  let mut i = 0;
+
+  while i < bytes.len() {
+    match bytes[i] {
+      b'%' => match (bytes.get(i + 1).and_then(hex_value), bytes.get(i + 2).and_then(hex_value)) {
+        (Some(high), Some(low)) => {
+          decoded.push(high << 4 | low);
+          i += 3;
+          continue;
+        },
+        _ => decoded.push(b'%')
+      },
+      b'+' if plus_as_space => decoded.push(b' '),
+      b => decoded.push(b)
+    }
+    i += 1;
+  }
+
+  Cow::Owned(decoded)
+}
+
+// Decodes a key or value of application/x-www-form-urlencoded data.
+// Bytes that don't make valid UTF-8 are replaced with U+FFFD.
+pub fn decode_form(s: &str) -> Cow<'_, str> {
+  match decode(s, true) {
+    Cow::Borrowed(_) => Cow::Borrowed(s),
+    Cow::Owned(bytes) => match String::from_utf8(bytes) {
+      Ok(decoded) => Cow::Owned(decoded),
+      Err(e) => Cow::Owned(String::from_utf8_lossy(e.as_bytes()).into_owned())
+    }
+  }
+}
+
+// Encodes a key or value for application/x-www-form-urlencoded data,
+// everything but letters, digits and "*-._" is escaped and spaces become "+".
+pub fn encode_form(s: &str) -> Cow<'_, str> {
+  if s.bytes().all(is_form_safe) {
+    return Cow::Borrowed(s);
+  }
+
+  let mut encoded = String::with_capacity(s.len() * 3);
+  for b in s.bytes() {
+    match b {
+      b' ' => encoded.push('+'),
+      b if is_form_safe(b) => encoded.push(b as char),
+      b => encoded.push_str(&format!("%{:02X}", b))
+    }
+  }
+  Cow::Owned(encoded)
+}
+
+// Encodes a single path segment for a URL, everything but the unreserved
+// characters of RFC 3986 is escaped, "/" included.
+pub fn encode_path_segment(s: &str) -> Cow<'_, str> {
+  let is_unreserved = |b: u8| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'.' | b'_' | b'~');
+  if s.bytes().all(is_unreserved) {
+    return Cow::Borrowed(s);
+  }
+
+  let mut encoded = String::with_capacity(s.len() * 3);
+  for b in s.bytes() {
+    if is_unreserved(b) {
+      encoded.push(b as char);
+    } else {
+      encoded.push_str(&format!("%{:02X}", b));
+    }
+  }
+  Cow::Owned(encoded)
+}
+
+fn is_form_safe(b: u8) -> bool {
+  b.is_ascii_alphanumeric() || matches!(b, b'*' | b'-' | b'.' | b'_')
+}
+
+fn hex_value(b: &u8) -> Option<u8> {
+  (*b as char).to_digit(16).map(|d| d as u8)
+}
@@ -1,50 +1,105 @@
-use std::{collections::HashMap};
+use std::{borrow::Cow, collections::HashMap, str::FromStr};
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use super::percent;
 
-#[derive(Debug)]
+// Keys and values are borrowed from the buffer when they didn't need any decoding,
+// and only copied into a String when they contained "%XX" or "+".
+#[derive(Clone, Debug)]
 
 pub struct QueryString<'buf> {
-  data: HashMap<&'buf str, Value<'buf>>
+  data: HashMap<Cow<'buf, str>, Value<'buf>>
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 
 pub enum Value<'buf> {
-    Single(&'buf str),
-    Multiple(Vec<&'buf str>)
+    Single(Cow<'buf, str>),
+    Multiple(Vec<Cow<'buf, str>>)
+}
+
+impl<'buf> Value<'buf> {
+  pub fn first(&self) -> &str {
+    match self {
+      Value::Single(val) => val,
+      Value::Multiple(vec) => &vec[0]
+    }
+  }
+
+  pub fn iter(&self) -> impl Iterator<Item = &str> {
+    let values = match self {
+      Value::Single(val) => std::slice::from_ref(val),
+      Value::Multiple(vec) => vec.as_slice()
+    };
+    values.iter().map(|val| val.as_ref())
+  }
 }
 
 impl<'buf> QueryString<'buf> {
-  pub fn get(&self, key: &str) -> Option<&Value> {
+  pub fn get(&self, key: &str) -> Option<&Value<'buf>> {
     self.data.get(key)
   }
+
+  // The first value of the key, parsed into any type that implements FromStr:
+  // query_string.get_parsed::<u32>("page")
+  pub fn get_parsed<T: FromStr>(&self, key: &str) -> Option<Result<T, T::Err>> {
+    self.get(key).map(|value| value.first().parse())
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.data.is_empty()
+  }
 }
 
+// Parses application/x-www-form-urlencoded data, the format of both query strings
+// and HTML form bodies:
+//
+// name=John%20Doe&tag=a+b&tag=c
 impl<'buf> From<&'buf str> for QueryString<'buf> {
   fn from(s: &'buf str) -> Self {
     let mut data = HashMap::new();
 
-    for sub_str in s.split('&') {
-      let mut key = sub_str;
-      let mut val = "";
+    for sub_str in s.split('&').filter(|sub_str| !sub_str.is_empty()) {
+      let (key, val) = sub_str.split_once('=').unwrap_or((sub_str, ""));
+      let key = percent::decode_form(key);
+      let val = percent::decode_form(val);
 
-      if let Some(i) = s.find('=') {
-        key = &sub_str[..i];
-        val = &sub_str[i + 1..];
-      }
-
-      data.entry(key).and_modify(|existing: &mut Value| match existing {
+      data.entry(key).and_modify(|existing: &mut Value<'buf>| match existing {
         Value::Single(prev_val) => {
           // let mut vec = vec![val, prev_val];
           // Vec::new();
           // vec.push(val);
           // vec.push(prev_val);
-          *existing = Value::Multiple(vec![prev_val, val]);
+          *existing = Value::Multiple(vec![prev_val.clone(), val.clone()]);
 
         }
-        Value::Multiple(vec) => vec.push(val)
+        Value::Multiple(vec) => vec.push(val.clone())
       }).or_insert(Value::Single(val));
     }
 
     QueryString { data }
   }
-}
\ No newline at end of file
+}
+
+// Turns key value pairs back into application/x-www-form-urlencoded data.
+pub fn encode<'a>(pairs: impl IntoIterator<Item = (&'a str, &'a str)>) -> String {
+  pairs
+    .into_iter()
+    .map(|(key, val)| format!("{}={}", percent::encode_form(key), percent::encode_form(val)))
+    .collect::<Vec<_>>()
+    .join("&")
+}
+
+// Writes the query string back out, the keys are sorted because the HashMap
+// doesn't remember the order they came in.
+impl Display for QueryString<'_> {
+  fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+    let mut keys: Vec<&Cow<str>> = self.data.keys().collect();
+    keys.sort();
+
+    let pairs = keys
+      .into_iter()
+      .flat_map(|key| self.data[key].iter().map(move |val| (key.as_ref(), val)));
+
+    write!(f, "{}", encode(pairs))
+  }
+}
@@ -0,0 +1,228 @@
+use super::conditional::{matches_etag, Validators};
+use super::{date, Part, Request};
+use std::ops::Range;
+
+// Requests with more ranges than this get the whole file, a long list of tiny
+// ranges costs us far more than it saves the client.
+const MAX_RANGES: usize = 32;
+
+// What to do with the Range header of a request.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ByteRanges {
+  // No Range header, or one we don't understand, send the whole representation.
+  Full,
+  // 416, none of the ranges overlap the representation.
+  Unsatisfiable,
+  // 206, send these parts. The ranges are end exclusive.
+  Partial(Vec<Range<u64>>),
+}
+
+// Parses a Range header for a representation of len bytes:
+//
+// Range: bytes=0-499        the first 500 bytes
+// Range: bytes=500-         everything from byte 500 on
+// Range: bytes=-500         the last 500 bytes
+// Range: bytes=0-0, -1      the first and the last byte
+//
+// A header with a syntax error is ignored, as RFC 9110 asks. If-Range makes the
+// ranges only apply when the client's copy is still the current one.
+pub fn byte_ranges(request: &Request, validators: &Validators, len: u64) -> ByteRanges {
+  let header = match request.headers().get("Range") {
+    Some(header) => header,
+    None => return ByteRanges::Full
+  };
+
+  if let Some(if_range) = request.headers().get("If-Range") {
+    if !if_range_matches(if_range, validators) {
+      return ByteRanges::Full;
+    }
+  }
+
+  match parse(header, len) {
+    Some(ranges) if ranges.is_empty() => ByteRanges::Unsatisfiable,
+    Some(ranges) => ByteRanges::Partial(ranges),
+    None => ByteRanges::Full
+  }
+}
+
+// Returns the satisfiable ranges, sorted and with overlapping or adjacent ones merged,
+// or None if the header is not a valid bytes range or asks for more than the whole
+// representation. "bytes=0-,0-,0-" would otherwise make us send it three times.
+fn parse(header: &str, len: u64) -> Option<Vec<Range<u64>>> {
+  let (unit, specs) = header.split_once('=')?;
+  if !unit.trim().eq_ignore_ascii_case("bytes") {
+    return None;
+  }
+
+  let specs: Vec<&str> = specs.split(',').map(str::trim).filter(|spec| !spec.is_empty()).collect();
+  if specs.is_empty() || specs.len() > MAX_RANGES {
+    return None;
+  }
+
+  let mut ranges = Vec::new();
+  for spec in specs {
+    let (first, last) = spec.split_once('-')?;
+
+    let range = if first.is_empty() {
+      // A suffix, the last n bytes.
+      let suffix: u64 = parse_number(last)?;
+      // The last bytes of an empty representation are none at all.
+      if suffix == 0 || len == 0 {
+        continue;
+      }
+      len.saturating_sub(suffix)..len
+    } else {
+      let first: u64 = parse_number(first)?;
+      let last = if last.is_empty() { u64::MAX } else { parse_number(last)? };
+      if last < first {
+        return None;
+      }
+      if first >= len {
+        continue;
+      }
+      first..last.saturating_add(1).min(len)
+    };
+
+    ranges.push(range);
+  }
+
+  let requested: u64 = ranges.iter().map(|range| range.end - range.start).sum();
+  if requested > len {
+    return None;
+  }
+
+  Some(merge(ranges))
+}
+
+fn merge(mut ranges: Vec<Range<u64>>) -> Vec<Range<u64>> {
+  ranges.sort_by_key(|range| range.start);
+
+  let mut merged: Vec<Range<u64>> = Vec::with_capacity(ranges.len());
+  for range in ranges {
+    match merged.last_mut() {
+      Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+      _ => merged.push(range)
+    }
+  }
+  merged
+}
+
+fn parse_number(s: &str) -> Option<u64> {
+  if s.is_empty() || !s.bytes().all(|b| b.is_ascii_digit()) {
+    return None;
+  }
+  s.parse().ok()
+}
+
+// If-Range holds either the ETag or the Last-Modified date the client has.
+// Only a strong match counts, a partial copy must be of exactly the same bytes.
+fn if_range_matches(if_range: &str, validators: &Validators) -> bool {
+  let if_range = if_range.trim();
+  if if_range.starts_with('"') || if_range.starts_with("W/") {
+    return !if_range.starts_with("W/") && matches_etag(if_range, &validators.etag, false);
+  }
+
+  date::parse(if_range) == Some(validators.last_modified)
+}
+
+pub fn content_range(range: &Range<u64>, len: u64) -> String {
+  format!("bytes {}-{}/{}", range.start, range.end - 1, len)
+}
+
+// Lays out a multipart/byteranges body, every range becomes a part with its own
+// Content-Type and Content-Range headers:
+//
+// --boundary\r\nContent-Type: text/css\r\nContent-Range: bytes 0-4/59\r\n\r\n* {\r\n\r\n--boundary--\r\n
+//
+// The ranges stay File parts, they are sent from the file when the response is,
+// len is its whole length.
+pub fn multipart(len: u64, ranges: &[Range<u64>], mime_type: &str, boundary: &str) -> Vec<Part> {
+  let mut parts = Vec::with_capacity(ranges.len() * 2 + 1);
+
+  for (i, range) in ranges.iter().enumerate() {
+    // The line break that ends the previous part goes with the next boundary.
+    let line_break = if i == 0 { "" } else { "\r\n" };
+    parts.push(Part::Bytes(format!(
+      "{}--{}\r\nContent-Type: {}\r\nContent-Range: {}\r\n\r\n",
+      line_break,
+      boundary,
+      mime_type,
+      content_range(range, len)
+    ).into_bytes()));
+    parts.push(Part::File { offset: range.start, len: range.end - range.start });
+  }
+  parts.push(Part::Bytes(format!("\r\n--{}--\r\n", boundary).into_bytes()));
+
+  parts
+}
+
+#[cfg(test)]
+// vec![0..500] is a list of one range here, not the numbers in it.
+#[allow(clippy::single_range_in_vec_init)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn parses_single_ranges() {
+    assert_eq!(parse("bytes=0-499", 1000), Some(vec![0..500]));
+    assert_eq!(parse("bytes=500-", 1000), Some(vec![500..1000]));
+    assert_eq!(parse("bytes=-500", 1000), Some(vec![500..1000]));
+    assert_eq!(parse("bytes=900-5000", 1000), Some(vec![900..1000]));
+  }
+
+  #[test]
+  fn drops_unsatisfiable_ranges() {
+    assert_eq!(parse("bytes=1000-", 1000), Some(vec![]));
+    assert_eq!(parse("bytes=-0", 1000), Some(vec![]));
+    assert_eq!(parse("bytes=0-0, 2000-3000", 1000), Some(vec![0..1]));
+  }
+
+  #[test]
+  fn nothing_is_satisfiable_in_an_empty_representation() {
+    assert_eq!(parse("bytes=-5", 0), Some(vec![]));
+    assert_eq!(parse("bytes=0-", 0), Some(vec![]));
+    assert_eq!(parse("bytes=0-0, -1", 0), Some(vec![]));
+  }
+
+  #[test]
+  fn ignores_invalid_headers() {
+    assert_eq!(parse("items=0-1", 1000), None);
+    assert_eq!(parse("bytes=5-1", 1000), None);
+    assert_eq!(parse("bytes=a-b", 1000), None);
+    assert_eq!(parse("bytes=+1-2", 1000), None);
+    assert_eq!(parse("bytes=", 1000), None);
+  }
+
+  #[test]
+  fn merges_overlapping_and_adjacent_ranges() {
+    assert_eq!(parse("bytes=0-10, 5-20", 1000), Some(vec![0..21]));
+    assert_eq!(parse("bytes=0-9, 10-19", 1000), Some(vec![0..20]));
+    assert_eq!(parse("bytes=500-599, 0-99", 1000), Some(vec![0..100, 500..600]));
+    assert_eq!(parse("bytes=0-0, -1", 1000), Some(vec![0..1, 999..1000]));
+  }
+
+  #[test]
+  fn ignores_ranges_asking_for_more_than_the_whole() {
+    assert_eq!(parse("bytes=0-,0-,0-", 1000), None);
+    assert_eq!(parse("bytes=0-599, 400-999", 1000), None);
+    assert_eq!(parse("bytes=0-499, 500-999", 1000), Some(vec![0..1000]));
+  }
+
+  #[test]
+  fn limits_the_number_of_ranges() {
+    let header = format!("bytes={}", (0..=MAX_RANGES).map(|i| format!("{}-{}", i, i)).collect::<Vec<_>>().join(","));
+    assert_eq!(parse(&header, 1000), None);
+  }
+
+  #[test]
+  fn lays_out_multipart_bodies() {
+    let parts = multipart(59, &[0..5, 50..59], "text/css", "b");
+    assert_eq!(parts, [
+      Part::Bytes(b"--b\r\nContent-Type: text/css\r\nContent-Range: bytes 0-4/59\r\n\r\n".to_vec()),
+      Part::File { offset: 0, len: 5 },
+      Part::Bytes(b"\r\n--b\r\nContent-Type: text/css\r\nContent-Range: bytes 50-58/59\r\n\r\n".to_vec()),
+      Part::File { offset: 50, len: 9 },
+      Part::Bytes(b"\r\n--b--\r\n".to_vec())
+    ]);
+  }
+}
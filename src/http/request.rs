@@ -1,42 +1,117 @@
 use super::method::{Method, MethodError};
+use std::borrow::Cow;
 use std::convert::TryFrom;
 use std::error::Error;
 use std::fmt::{Display, Debug, Formatter, Result as FmtResult};
 use std::str::{self, Utf8Error};
-use super::QueryString;
+use super::{chunked, path, Headers, QueryString, StatusCode};
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct Request<'buf> {
-    path: &'buf str,
+    // The request target exactly as the client sent it, query string included.
+    target: &'buf str,
+    raw_path: &'buf str,
+    // The decoded and normalized path, see path::normalize().
+    path: Cow<'buf, str>,
     query_string: Option<QueryString<'buf>>,
     method: Method,
+    headers: Headers<'buf>,
+    // Borrowed from the buffer, unless the body had to be decoded.
+    body: Cow<'buf, [u8]>,
+    trailers: Headers<'buf>,
+    // Path parameters, filled in by the Router when the path matches a pattern like /users/:id.
+    params: Vec<(String, String)>,
+    // A HEAD request the server passes on as GET, see head_as_get().
+    head: bool,
 }
 
 impl<'buf> Request<'buf> {
+    // The decoded path with "." and ".." resolved, this is what routing and
+    // file lookups should use.
     pub fn path(&self) -> &str {
         &self.path
     }
 
+    // The path as it was sent, still percent-encoded.
+    pub fn raw_path(&self) -> &'buf str {
+        self.raw_path
+    }
+
+    // The whole request target as it was sent, for proxying and logging.
+    pub fn target(&self) -> &'buf str {
+        self.target
+    }
+
     pub fn method(&self) -> &Method {
         &self.method
     }
 
-    pub fn query_string(&self) -> Option<&QueryString> {
+    pub fn query_string(&self) -> Option<&QueryString<'buf>> {
         self.query_string.as_ref()
     }
-}
 
-impl<'buf> Request<'buf> {
-    fn from_byte_array(buf: &[u8]) -> Result<Self, String> {
-        unimplemented!()
+    pub fn headers(&self) -> &Headers<'buf> {
+        &self.headers
+    }
+
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+
+    // Headers sent after a chunked body.
+    pub fn trailers(&self) -> &Headers<'buf> {
+        &self.trailers
+    }
+
+    // The fields of an HTML form sent as application/x-www-form-urlencoded.
+    pub fn form(&self) -> Option<QueryString<'_>> {
+        let content_type = self.headers.get("Content-Type")?;
+        let mime_type = content_type.split(';').next().unwrap_or("").trim();
+        if !mime_type.eq_ignore_ascii_case("application/x-www-form-urlencoded") {
+            return None;
+        }
+
+        str::from_utf8(&self.body).ok().map(QueryString::from)
+    }
+
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn params(&self) -> &[(String, String)] {
+        &self.params
+    }
+
+    // The server answers HEAD requests with the GET handler, this is the copy
+    // it hands over. is_head() still tells the two apart.
+    pub fn head_as_get(&self) -> Self {
+        Self { method: Method::GET, head: true, ..self.clone() }
+    }
+
+    // Whether the client only wants the headers. Handlers only see HEAD requests
+    // as GET, this is for the work that is wasted on them, or that the RFC only
+    // defines for GET, like Range.
+    pub fn is_head(&self) -> bool {
+        self.head
+    }
+
+    // A copy of the request with the path parameters the router extracted.
+    pub fn with_params(&self, params: Vec<(String, String)>) -> Self {
+        Self { params, ..self.clone() }
     }
 }
 
 impl<'buf> TryFrom<&'buf [u8]> for Request<'buf> {
     type Error = ParseError;
 
-    // GET /search?name=abc&sort=1 HTTP/1.1\r\n...HEADERS...
+    // GET /search?name=abc&sort=1 HTTP/1.1\r\n...HEADERS...\r\n\r\n...BODY...
     fn try_from(buf: &'buf [u8]) -> Result<Self, Self::Error> {
+        // Only the head of the request has to be text, the body can be any bytes.
+        let head_end = find_head_end(buf).ok_or(ParseError::InvalidRequest)?;
+
         // match str::from_utf8(buf) {
         //     Ok(request) => {},
         //     Err(err) => return Err(ParseError::InvalidEncoding)
@@ -51,11 +126,12 @@ impl<'buf> TryFrom<&'buf [u8]> for Request<'buf> {
         // but in this case it returns the error from the whole function
         // also, the error value go through the from function defined
         // in the From trait
-        let request = str::from_utf8(buf)?;
+        let request = str::from_utf8(&buf[..head_end])?;
+
+        let (request_line, header_block) = split_request_line(request);
 
-        let (method, request) = get_next_word(request).ok_or(ParseError::InvalidRequest)?;
-        let (mut path, request) = get_next_word(request).ok_or(ParseError::InvalidRequest)?;
-        let (protocol, _) = get_next_word(request).ok_or(ParseError::InvalidRequest)?;
+        let (method, request_line) = get_next_word(request_line).ok_or(ParseError::InvalidRequest)?;
+        let (target, protocol) = get_next_word(request_line).ok_or(ParseError::InvalidRequest)?;
 
         if protocol != "HTTP/1.1" {
             return Err(ParseError::InvalidProtocol);
@@ -79,22 +155,202 @@ impl<'buf> TryFrom<&'buf [u8]> for Request<'buf> {
         //     path = &path[..i];
         // }
 
+        let mut path = origin_form(target);
         if let Some(i) = path.find("?") {
             query_string = Some(QueryString::from(&path[i + 1..]));
             path = &path[..i];
         }
 
+        let headers = Headers::try_from(header_block)?;
+
+        let body_start = head_end + 4;
+        let (body, trailers) = match body_framing(&headers)? {
+            Framing::Length(length) => {
+                let body_end = body_start
+                    .checked_add(length)
+                    .ok_or(ParseError::InvalidContentLength)?;
+                let body = buf.get(body_start..body_end).ok_or(ParseError::IncompleteBody)?;
+                (Cow::Borrowed(body), Headers::default())
+            }
+            Framing::Chunked => {
+                let decoded = chunked::decode(&buf[body_start..])?;
+                (Cow::Owned(decoded.body), decoded.trailers)
+            }
+        };
+
         Ok(Self {
-            path,
+            target,
+            raw_path: path,
+            path: path::normalize(path)?,
             query_string,
-            method
+            method,
+            headers,
+            body,
+            trailers,
+            params: Vec::new(),
+            head: false
         })
     }
 }
 
+// Requests (head and body together) larger than this are rejected instead of
+// being buffered, so a single client can't make the server allocate without limit.
+pub const MAX_REQUEST_SIZE: usize = 8 * 1024 * 1024;
+
+// Keeps track of a request while its bytes are still arriving from the connection,
+// so the head is searched for and parsed once, and a chunked body is gone through
+// once, instead of again on every read.
+#[derive(Default)]
+pub struct PendingRequest {
+    // How much of the buffer has already been searched for the end of the head.
+    scanned: usize,
+    // Where the body starts and how its end is found, once the head is complete.
+    body: Option<(usize, Framing)>,
+    // How far the chunks of a chunked body have been read.
+    chunked: chunked::Progress,
+}
+
+impl PendingRequest {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Looks at the bytes read so far and returns the length of the first complete
+    // request (head and body) in the buffer, or None if more bytes have to be read.
+    // The buffer may only grow between calls, reset() starts over with the next request.
+    pub fn length(&mut self, buf: &[u8]) -> Result<Option<usize>, ParseError> {
+        let (body_start, framing) = match self.body {
+            Some(body) => body,
+            None => {
+                // The "\r\n\r\n" may have been split between two reads.
+                let from = self.scanned.saturating_sub(3);
+                let head_end = match find_head_end(&buf[from..]) {
+                    Some(i) => from + i,
+                    None => {
+                        self.scanned = buf.len();
+                        return Ok(None);
+                    }
+                };
+
+                let request = str::from_utf8(&buf[..head_end])?;
+                let (_, header_block) = split_request_line(request);
+                let headers = Headers::try_from(header_block)?;
+
+                let body = (head_end + 4, body_framing(&headers)?);
+                self.body = Some(body);
+                body
+            }
+        };
+
+        match framing {
+            Framing::Length(length) => {
+                let length = body_start
+                    .checked_add(length)
+                    .ok_or(ParseError::InvalidContentLength)?;
+
+                // No need to wait for 8 MiB to arrive when the head already says it will.
+                if length > MAX_REQUEST_SIZE {
+                    return Err(ParseError::RequestTooLarge);
+                }
+
+                if buf.len() >= length {
+                    Ok(Some(length))
+                } else {
+                    Ok(None)
+                }
+            }
+            Framing::Chunked => {
+                let max_len = MAX_REQUEST_SIZE.saturating_sub(body_start);
+                Ok(chunked::length(&buf[body_start..], max_len, &mut self.chunked)?.map(|length| body_start + length))
+            }
+        }
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
+
+// How the end of the body is found.
+#[derive(Clone, Copy)]
+enum Framing {
+    Length(usize),
+    Chunked,
+}
+
+fn body_framing(headers: &Headers) -> Result<Framing, ParseError> {
+    if !headers.contains("Transfer-Encoding") {
+        return Ok(Framing::Length(content_length(headers)?));
+    }
+
+    // A message with both headers is a common way to smuggle requests past
+    // proxies that pick the other one, so it is rejected instead of guessed.
+    if headers.contains("Content-Length") {
+        return Err(ParseError::InvalidContentLength);
+    }
+
+    // chunked has to be the last coding, otherwise the length of the body can't be known.
+    let last_coding = headers
+        .get_all("Transfer-Encoding")
+        .flat_map(|v| v.split(','))
+        .map(str::trim)
+        .filter(|coding| !coding.is_empty())
+        .last();
+
+    match last_coding {
+        Some(coding) if coding.eq_ignore_ascii_case("chunked") => Ok(Framing::Chunked),
+        _ => Err(ParseError::UnsupportedTransferEncoding),
+    }
+}
+
+// Index of the empty line "\r\n\r\n" that separates the head from the body.
+fn find_head_end(buf: &[u8]) -> Option<usize> {
+    buf.windows(4).position(|window| window == b"\r\n\r\n")
+}
+
+// Splits the head into the request line and the header block.
+fn split_request_line(head: &str) -> (&str, &str) {
+    head.split_once("\r\n").unwrap_or((head, ""))
+}
+
+// A request without Content-Length has no body. Repeated values are only
+// accepted when they all agree, anything else could be used to smuggle requests.
+fn content_length(headers: &Headers) -> Result<usize, ParseError> {
+    let mut length = None;
+
+    for value in headers.get_all("Content-Length").flat_map(|v| v.split(',')) {
+        let value = value.trim();
+        if value.is_empty() || !value.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(ParseError::InvalidContentLength);
+        }
+
+        let value: usize = value.parse().map_err(|_| ParseError::InvalidContentLength)?;
+        if length.is_some_and(|l| l != value) {
+            return Err(ParseError::InvalidContentLength);
+        }
+        length = Some(value);
+    }
+
+    Ok(length.unwrap_or(0))
+}
+
+// Requests sent to a proxy carry the whole URL, "GET http://example.com/about HTTP/1.1".
+// Only the path and query of it matter to us.
+fn origin_form(target: &str) -> &str {
+    let rest = match target.strip_prefix("http://").or_else(|| target.strip_prefix("https://")) {
+        Some(rest) => rest,
+        None => return target,
+    };
+
+    match rest.find(['/', '?']) {
+        Some(i) if rest[i..].starts_with('/') => &rest[i..],
+        _ => "/",
+    }
+}
+
 fn get_next_word(request: &str) -> Option<(&str, &str)> {
-    for (i, c) in request.chars().enumerate() {
-        if c == ' ' || c == '\r' {
+    for (i, c) in request.char_indices() {
+        if c == ' ' {
             return Some((&request[..i], &request[i + 1..]));
         }
     }
@@ -106,6 +362,16 @@ pub enum ParseError {
     InvalidEncoding,
     InvalidProtocol,
     InvalidMethod,
+    InvalidHeaderName,
+    InvalidHeaderValue,
+    MalformedHeader,
+    InvalidContentLength,
+    IncompleteBody,
+    RequestTooLarge,
+    InvalidChunkSize,
+    MalformedChunk,
+    UnsupportedTransferEncoding,
+    InvalidPath,
 }
 
 impl ParseError {
@@ -115,6 +381,26 @@ impl ParseError {
             Self::InvalidEncoding => "INVALID_ENCODING",
             Self::InvalidProtocol => "INVALID_PROTOCOL",
             Self::InvalidMethod => "INVALID_METHOD",
+            Self::InvalidHeaderName => "INVALID_HEADER_NAME",
+            Self::InvalidHeaderValue => "INVALID_HEADER_VALUE",
+            Self::MalformedHeader => "MALFORMED_HEADER",
+            Self::InvalidContentLength => "INVALID_CONTENT_LENGTH",
+            Self::IncompleteBody => "INCOMPLETE_BODY",
+            Self::RequestTooLarge => "REQUEST_TOO_LARGE",
+            Self::InvalidChunkSize => "INVALID_CHUNK_SIZE",
+            Self::MalformedChunk => "MALFORMED_CHUNK",
+            Self::UnsupportedTransferEncoding => "UNSUPPORTED_TRANSFER_ENCODING",
+            Self::InvalidPath => "INVALID_PATH",
+        }
+    }
+
+    // The status code to answer the request with.
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            Self::InvalidProtocol => StatusCode::HttpVersionNotSupported,
+            Self::InvalidMethod | Self::UnsupportedTransferEncoding => StatusCode::NotImplemented,
+            Self::RequestTooLarge => StatusCode::ContentTooLarge,
+            _ => StatusCode::BadRequest,
         }
     }
 }
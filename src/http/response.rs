@@ -1,23 +1,287 @@
-use super::StatusCode;
-use std::io::{Write, Result as IoResult};
-use std::{fmt::{Display, Formatter, Result as FmtResult}, net::TcpStream};
+use super::{date, StatusCode};
+use super::chunked::ChunkedWriter;
+use std::fmt::{Debug, Formatter, Result as FmtResult};
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write, Result as IoResult};
+use std::time::SystemTime;
 
 #[derive(Debug)]
 pub struct Response {
   status_code: StatusCode,
-  body: Option<String>
+  // Kept in the order they were added, names compare case-insensitively.
+  headers: Vec<(String, String)>,
+  body: Body,
+  keep_alive: bool,
+  // Answering a HEAD request, the headers are sent but the body isn't.
+  omit_body: bool
 }
 
-impl Response {
-  pub fn new(status_code: StatusCode, body: Option<String>) -> Self {
-    Response { status_code, body }
+enum Body {
+  Empty,
+  // Any bytes, so images, fonts and other binary files can be sent as well.
+  Bytes(Vec<u8>),
+  // A body whose length is not known up front, it is sent with chunked encoding.
+  Stream(Box<dyn Read + Send>),
+  // Part of a file, sent straight from the file without reading it into memory.
+  File { file: File, offset: u64, len: u64 },
+  // Bytes with pieces of a file between them, like the parts of a multipart/byteranges
+  // body. The pieces are sent the way a File body is.
+  Parts { file: File, parts: Vec<Part> }
+}
+
+// A piece of a Parts body.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Part {
+  Bytes(Vec<u8>),
+  // len bytes of the file starting at offset.
+  File { offset: u64, len: u64 }
+}
+
+impl Part {
+  fn len(&self) -> u64 {
+    match self {
+      Self::Bytes(bytes) => bytes.len() as u64,
+      Self::File { len, .. } => *len
+    }
+  }
+}
+
+impl Debug for Body {
+  fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+    match self {
+      Self::Empty => write!(f, "Empty"),
+      Self::Bytes(bytes) => write!(f, "Bytes({} bytes)", bytes.len()),
+      Self::Stream(_) => write!(f, "Stream"),
+      Self::File { offset, len, .. } => write!(f, "File({} bytes from {})", len, offset),
+      Self::Parts { parts, .. } => write!(f, "Parts({} parts)", parts.len())
+    }
   }
+}
 
-  pub fn send(&self, stream: &mut dyn Write) -> IoResult<()> {
-    let body = match &self.body {
-      Some(b) => b,
-      None => ""
+impl Response {
+  pub fn new(status_code: StatusCode, body: Option<Vec<u8>>) -> Self {
+    let body = match body {
+      Some(bytes) => Body::Bytes(bytes),
+      None => Body::Empty
     };
-    write!(stream, "HTTP/1.1 {} {}\r\n\r\n{}", self.status_code, self.status_code.reason_phrase(), body)
+    Response { status_code, headers: Vec::new(), body, keep_alive: true, omit_body: false }
+  }
+
+  // Streams the body from the reader until it runs out.
+  pub fn stream(status_code: StatusCode, reader: impl Read + Send + 'static) -> Self {
+    Response { status_code, headers: Vec::new(), body: Body::Stream(Box::new(reader)), keep_alive: true, omit_body: false }
+  }
+
+  // Sends len bytes of the file starting at offset. However big the file is,
+  // it is never read into memory, sockets get it with sendfile.
+  pub fn file(status_code: StatusCode, file: File, offset: u64, len: u64) -> Self {
+    Response { status_code, headers: Vec::new(), body: Body::File { file, offset, len }, keep_alive: true, omit_body: false }
+  }
+
+  // Sends the parts one after the other, File parts come from the file like
+  // the body of Response::file() does.
+  pub fn parts(status_code: StatusCode, file: File, parts: Vec<Part>) -> Self {
+    Response { status_code, headers: Vec::new(), body: Body::Parts { file, parts }, keep_alive: true, omit_body: false }
+  }
+
+  pub fn status_code(&self) -> StatusCode {
+    self.status_code
+  }
+
+  // The body when it is held in memory, None when there is none, it is streamed
+  // or sent from a file. buffer_body() reads a file body into memory.
+  pub fn body(&self) -> Option<&[u8]> {
+    match &self.body {
+      Body::Bytes(bytes) => Some(bytes),
+      _ => None
+    }
+  }
+
+  // The length of the body, None for a stream, which only knows it at the end.
+  pub fn body_len(&self) -> Option<u64> {
+    match &self.body {
+      Body::Empty => Some(0),
+      Body::Bytes(bytes) => Some(bytes.len() as u64),
+      Body::Stream(_) => None,
+      Body::File { len, .. } => Some(*len),
+      Body::Parts { parts, .. } => Some(parts.iter().map(Part::len).sum())
+    }
+  }
+
+  // Reads a file body into memory, for middleware that has to change it.
+  pub fn buffer_body(&mut self) -> IoResult<()> {
+    let len = self.body_len().unwrap_or(0);
+    let mut bytes = Vec::with_capacity(len as usize);
+    match &mut self.body {
+      Body::File { file, offset, len } => copy_file(&mut bytes, file, *offset, *len)?,
+      Body::Parts { file, parts } => send_parts(&mut bytes, file, parts)?,
+      _ => return Ok(())
+    }
+    self.body = Body::Bytes(bytes);
+    Ok(())
+  }
+
+  pub fn set_body(&mut self, body: Vec<u8>) {
+    self.body = Body::Bytes(body);
+  }
+
+  // Returns the first value of the header.
+  pub fn header(&self, name: &str) -> Option<&str> {
+    self.headers
+      .iter()
+      .find(|(key, _)| key.eq_ignore_ascii_case(name))
+      .map(|(_, value)| value.as_str())
+  }
+
+  // Replaces every value the header had so far.
+  pub fn set_header(&mut self, name: &str, value: &str) {
+    self.remove_header(name);
+    self.append_header(name, value);
+  }
+
+  // Adds another value for the header, for headers like Set-Cookie that can repeat.
+  pub fn append_header(&mut self, name: &str, value: &str) {
+    // A line break in a header would let whoever controls the value write their
+    // own headers or body into the response.
+    if name.is_empty() || name.contains(|c: char| c.is_ascii_control() || c == ':' || c == ' ')
+      || value.contains(['\r', '\n']) {
+      println!("Ignoring invalid response header: {:?}", name);
+      return;
+    }
+
+    self.headers.push((name.to_string(), value.to_string()));
+  }
+
+  // Adds a request header the response depends on to Vary, unless it is listed already.
+  pub fn add_vary(&mut self, name: &str) {
+    let listed = self.headers
+      .iter()
+      .filter(|(key, _)| key.eq_ignore_ascii_case("Vary"))
+      .flat_map(|(_, value)| value.split(','))
+      .any(|value| value.trim() == "*" || value.trim().eq_ignore_ascii_case(name));
+    if !listed {
+      self.append_header("Vary", name);
+    }
+  }
+
+  pub fn remove_header(&mut self, name: &str) {
+    self.headers.retain(|(key, _)| !key.eq_ignore_ascii_case(name));
+  }
+
+  // Tells the client whether the connection stays open after this response.
+  pub fn set_keep_alive(&mut self, keep_alive: bool) {
+    self.keep_alive = keep_alive;
   }
-}
\ No newline at end of file
+
+  // The headers still describe the body, Content-Length included, as if it was sent.
+  pub fn set_omit_body(&mut self, omit_body: bool) {
+    self.omit_body = omit_body;
+  }
+
+  // A handler can also close the connection by setting "Connection: close" itself.
+  pub fn keep_alive(&self) -> bool {
+    self.keep_alive && !self.header("Connection").is_some_and(|value| value.eq_ignore_ascii_case("close"))
+  }
+
+  pub fn send(&mut self, stream: &mut dyn Transmit) -> IoResult<()> {
+    write!(stream, "HTTP/1.1 {} {}\r\n", self.status_code, self.status_code.reason_phrase())?;
+
+    if self.header("Date").is_none() {
+      write!(stream, "Date: {}\r\n", date::format(SystemTime::now()))?;
+    }
+    if self.header("Server").is_none() {
+      write!(stream, "Server: {}/{}\r\n", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"))?;
+    }
+
+    // The framing headers always come from the body, a stale value set by a
+    // handler would make the client read the wrong number of bytes.
+    for (name, value) in &self.headers {
+      if name.eq_ignore_ascii_case("Content-Length")
+        || name.eq_ignore_ascii_case("Transfer-Encoding")
+        || name.eq_ignore_ascii_case("Connection") {
+        continue;
+      }
+      write!(stream, "{}: {}\r\n", name, value)?;
+    }
+
+    if !self.keep_alive() {
+      write!(stream, "Connection: close\r\n")?;
+    }
+
+    if !self.status_code.allows_body() {
+      write!(stream, "\r\n")?;
+      return stream.flush();
+    }
+
+    // On a persistent connection the client can only find the end of the body
+    // from its length, or from the chunks when streaming.
+    match &mut self.body {
+      Body::Empty => write!(stream, "Content-Length: 0\r\n\r\n")?,
+      Body::Bytes(bytes) => {
+        write!(stream, "Content-Length: {}\r\n\r\n", bytes.len())?;
+        if !self.omit_body {
+          stream.write_all(bytes)?;
+        }
+      },
+      Body::Stream(_) if self.omit_body => write!(stream, "Transfer-Encoding: chunked\r\n\r\n")?,
+      Body::Stream(reader) => {
+        write!(stream, "Transfer-Encoding: chunked\r\n\r\n")?;
+
+        let mut writer = ChunkedWriter::new(stream);
+        io::copy(reader, &mut writer)?;
+        return writer.finish();
+      },
+      Body::File { file, offset, len } => {
+        write!(stream, "Content-Length: {}\r\n\r\n", len)?;
+        if !self.omit_body {
+          stream.flush()?;
+          stream.send_file(file, *offset, *len)?;
+        }
+      },
+      Body::Parts { file, parts } => {
+        let len: u64 = parts.iter().map(Part::len).sum();
+        write!(stream, "Content-Length: {}\r\n\r\n", len)?;
+        if !self.omit_body {
+          send_parts(stream, file, parts)?;
+        }
+      }
+    }
+    stream.flush()
+  }
+}
+
+// Where responses are sent. Anything that can be written to works, but some
+// destinations can send a file better than by copying it through a buffer.
+pub trait Transmit: Write {
+  // Sends len bytes of the file from offset on.
+  fn send_file(&mut self, file: &mut File, offset: u64, len: u64) -> IoResult<()> {
+    copy_file(self, file, offset, len)
+  }
+}
+
+impl Transmit for Vec<u8> {}
+
+fn send_parts<T: Transmit + ?Sized>(stream: &mut T, file: &mut File, parts: &[Part]) -> IoResult<()> {
+  for part in parts {
+    match part {
+      Part::Bytes(bytes) => stream.write_all(bytes)?,
+      Part::File { offset, len } => {
+        stream.flush()?;
+        stream.send_file(file, *offset, *len)?;
+      }
+    }
+  }
+  Ok(())
+}
+
+// Sends part of a file the portable way, a buffer at a time.
+pub fn copy_file<W: Write + ?Sized>(writer: &mut W, file: &mut File, offset: u64, len: u64) -> IoResult<()> {
+  file.seek(SeekFrom::Start(offset))?;
+  let copied = io::copy(&mut file.take(len), writer)?;
+  // The file got shorter since the Content-Length was sent, the client
+  // can't be told anymore, the connection has to be closed.
+  if copied != len {
+    return Err(io::ErrorKind::UnexpectedEof.into());
+  }
+  Ok(())
+}
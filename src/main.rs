@@ -1,9 +1,26 @@
 #![allow(dead_code)]
+#![allow(clippy::upper_case_acronyms)]
 
+mod autoindex;
+mod compression;
+mod cors;
+mod deflate;
+#[cfg(target_os = "linux")]
+mod event_loop;
 mod http;
+mod middleware;
+mod mime;
+#[cfg(target_os = "linux")]
+mod poll;
+mod router;
+mod sendfile;
 mod server;
+mod static_files;
+mod thread_pool;
 mod website_handler;
 
+use compression::Compression;
+use cors::Cors;
 use server::Server;
 use website_handler::WebsiteHandler;
 use std::env;
@@ -14,6 +31,29 @@ fn main() {
 
     println!("Public path: {}", public_path);
 
-    let server = Server::new("127.0.0.1:8080".to_string());
-    server.run(WebsiteHandler::new(public_path));
+    let server = Server::new("127.0.0.1:8080".to_string()).middleware(Compression::new());
+
+    // CORS_ORIGINS=https://app.example.com,https://*.example.com lets scripts on
+    // those origins read from the site.
+    let server = match env::var("CORS_ORIGINS") {
+        Ok(origins) => {
+            let cors = origins
+                .split(',')
+                .map(str::trim)
+                .filter(|origin| !origin.is_empty())
+                .fold(Cors::new(), |cors, origin| cors.allow_origin(origin));
+            server.middleware(cors)
+        },
+        Err(_) => server
+    };
+
+    // SERVER_MODE=epoll serves connections from event loops instead of the thread pool.
+    #[cfg(target_os = "linux")]
+    let server = match env::var("SERVER_MODE").as_deref() {
+        Ok("epoll") => server.mode(server::Mode::EventLoop),
+        _ => server
+    };
+
+    // /about is served from about.html.
+    server.run(WebsiteHandler::new(public_path).html_extension(true));
 }
\ No newline at end of file
@@ -0,0 +1,62 @@
+use crate::http::{ParseError, Request, Response};
+use super::server::Handler;
+
+// Middleware runs around a handler. It can look at the request and answer it
+// itself, without ever calling next, or pass it on with next.handle_request()
+// and change the response that comes back.
+pub trait Middleware: Send + Sync {
+  fn handle(&self, request: &Request, next: &dyn Handler) -> Response;
+}
+
+// Closures can be used as middleware as well:
+// |request: &Request, next: &dyn Handler| next.handle_request(request)
+impl<F> Middleware for F
+where
+  F: Fn(&Request, &dyn Handler) -> Response + Send + Sync,
+{
+  fn handle(&self, request: &Request, next: &dyn Handler) -> Response {
+    self(request, next)
+  }
+}
+
+// A handler wrapped in a stack of middleware. The first middleware in the
+// stack sees the request first and the response last.
+pub struct Chain<H: Handler> {
+  middleware: Vec<Box<dyn Middleware>>,
+  handler: H
+}
+
+impl<H: Handler> Chain<H> {
+  pub fn new(middleware: Vec<Box<dyn Middleware>>, handler: H) -> Self {
+    Self { middleware, handler }
+  }
+}
+
+impl<H: Handler> Handler for Chain<H> {
+  fn handle_request(&self, request: &Request) -> Response {
+    Next { middleware: &self.middleware, handler: &self.handler }.handle_request(request)
+  }
+
+  fn handle_bad_request(&self, e: &ParseError) -> Response {
+    self.handler.handle_bad_request(e)
+  }
+}
+
+// The rest of the stack after the current middleware, ending with the handler.
+struct Next<'a> {
+  middleware: &'a [Box<dyn Middleware>],
+  handler: &'a dyn Handler
+}
+
+impl Handler for Next<'_> {
+  fn handle_request(&self, request: &Request) -> Response {
+    match self.middleware.split_first() {
+      Some((middleware, rest)) => middleware.handle(request, &Next { middleware: rest, handler: self.handler }),
+      None => self.handler.handle_request(request)
+    }
+  }
+
+  fn handle_bad_request(&self, e: &ParseError) -> Response {
+    self.handler.handle_bad_request(e)
+  }
+}
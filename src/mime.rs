@@ -0,0 +1,81 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+// Files we don't know the type of are sent as plain bytes, browsers will offer to download them.
+const DEFAULT_MIME_TYPE: &str = "application/octet-stream";
+
+// Maps file extensions to the Content-Type they are served with.
+// The built-in table covers the common web types, entries added with insert()
+// take precedence over it.
+#[derive(Debug, Default)]
+pub struct MimeTypes {
+  custom: HashMap<String, String>
+}
+
+impl MimeTypes {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn insert(&mut self, extension: &str, mime_type: &str) {
+    let extension = extension.trim_start_matches('.').to_ascii_lowercase();
+    self.custom.insert(extension, mime_type.to_string());
+  }
+
+  pub fn get(&self, path: impl AsRef<Path>) -> &str {
+    let extension = match path.as_ref().extension().and_then(|e| e.to_str()) {
+      Some(extension) => extension.to_ascii_lowercase(),
+      None => return DEFAULT_MIME_TYPE
+    };
+
+    match self.custom.get(&extension) {
+      Some(mime_type) => mime_type,
+      None => builtin(&extension).unwrap_or(DEFAULT_MIME_TYPE)
+    }
+  }
+}
+
+fn builtin(extension: &str) -> Option<&'static str> {
+  let mime_type = match extension {
+    // Text types carry a charset, otherwise browsers guess the encoding.
+    "html" | "htm" => "text/html; charset=utf-8",
+    "css" => "text/css; charset=utf-8",
+    "js" | "mjs" => "text/javascript; charset=utf-8",
+    "json" | "map" => "application/json",
+    "webmanifest" => "application/manifest+json",
+    "txt" => "text/plain; charset=utf-8",
+    "csv" => "text/csv; charset=utf-8",
+    "md" => "text/markdown; charset=utf-8",
+    "xml" => "application/xml",
+    "rss" => "application/rss+xml",
+    "atom" => "application/atom+xml",
+
+    "png" => "image/png",
+    "jpg" | "jpeg" => "image/jpeg",
+    "gif" => "image/gif",
+    "webp" => "image/webp",
+    "avif" => "image/avif",
+    "svg" => "image/svg+xml",
+    "ico" => "image/x-icon",
+    "bmp" => "image/bmp",
+
+    "woff" => "font/woff",
+    "woff2" => "font/woff2",
+    "ttf" => "font/ttf",
+    "otf" => "font/otf",
+
+    "mp3" => "audio/mpeg",
+    "ogg" => "audio/ogg",
+    "wav" => "audio/wav",
+    "mp4" => "video/mp4",
+    "webm" => "video/webm",
+
+    "pdf" => "application/pdf",
+    "zip" => "application/zip",
+    "gz" => "application/gzip",
+    "wasm" => "application/wasm",
+    _ => return None
+  };
+
+  Some(mime_type)
+}
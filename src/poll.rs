@@ -0,0 +1,142 @@
+// A small wrapper around Linux epoll. The functions come from the C library that
+// std already links against, so no extra crate is needed, we only declare them.
+use std::io::{Error, ErrorKind, Result as IoResult};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::time::Duration;
+
+const EPOLL_CLOEXEC: i32 = 0o2000000;
+
+const EPOLL_CTL_ADD: i32 = 1;
+const EPOLL_CTL_DEL: i32 = 2;
+const EPOLL_CTL_MOD: i32 = 3;
+
+const EPOLLIN: u32 = 0x001;
+const EPOLLOUT: u32 = 0x004;
+const EPOLLERR: u32 = 0x008;
+const EPOLLHUP: u32 = 0x010;
+const EPOLLRDHUP: u32 = 0x2000;
+const EPOLLEXCLUSIVE: u32 = 1 << 28;
+
+// The kernel packs this struct on x86_64, so we have to as well.
+#[repr(C)]
+#[cfg_attr(target_arch = "x86_64", repr(packed))]
+#[derive(Clone, Copy)]
+struct EpollEvent {
+  events: u32,
+  data: u64,
+}
+
+extern "C" {
+  fn epoll_create1(flags: i32) -> i32;
+  fn epoll_ctl(epfd: i32, op: i32, fd: i32, event: *mut EpollEvent) -> i32;
+  fn epoll_wait(epfd: i32, events: *mut EpollEvent, maxevents: i32, timeout: i32) -> i32;
+}
+
+#[derive(Clone, Copy)]
+pub enum Interest {
+  Read,
+  Write,
+  // Only one of the threads waiting on the same listener is woken up per connection.
+  ExclusiveRead,
+}
+
+impl Interest {
+  fn events(self) -> u32 {
+    match self {
+      Self::Read => EPOLLIN | EPOLLRDHUP,
+      Self::Write => EPOLLOUT,
+      Self::ExclusiveRead => EPOLLIN | EPOLLEXCLUSIVE,
+    }
+  }
+}
+
+pub struct Poll {
+  fd: OwnedFd,
+}
+
+impl Poll {
+  pub fn new() -> IoResult<Self> {
+    let fd = check(unsafe { epoll_create1(EPOLL_CLOEXEC) })?;
+    // The OwnedFd closes the epoll instance when the Poll is dropped.
+    Ok(Self { fd: unsafe { OwnedFd::from_raw_fd(fd) } })
+  }
+
+  // Registers the file descriptor, the events for it are reported with its fd as the token.
+  pub fn add(&self, fd: &impl AsRawFd, interest: Interest) -> IoResult<()> {
+    self.ctl(EPOLL_CTL_ADD, fd.as_raw_fd(), interest.events())
+  }
+
+  pub fn modify(&self, fd: &impl AsRawFd, interest: Interest) -> IoResult<()> {
+    self.ctl(EPOLL_CTL_MOD, fd.as_raw_fd(), interest.events())
+  }
+
+  pub fn delete(&self, fd: &impl AsRawFd) -> IoResult<()> {
+    self.ctl(EPOLL_CTL_DEL, fd.as_raw_fd(), 0)
+  }
+
+  // Waits until some of the registered file descriptors are ready or the timeout runs out.
+  pub fn wait(&self, events: &mut Events, timeout: Duration) -> IoResult<()> {
+    let timeout = timeout.as_millis().min(i32::MAX as u128) as i32;
+    let capacity = events.list.capacity().min(i32::MAX as usize) as i32;
+
+    let n = unsafe { epoll_wait(self.fd.as_raw_fd(), events.list.as_mut_ptr(), capacity, timeout) };
+    match check(n) {
+      // The kernel filled the first n entries.
+      Ok(n) => unsafe { events.list.set_len(n as usize) },
+      Err(e) if e.kind() == ErrorKind::Interrupted => events.list.clear(),
+      Err(e) => return Err(e),
+    }
+    Ok(())
+  }
+
+  fn ctl(&self, op: i32, fd: RawFd, events: u32) -> IoResult<()> {
+    let mut event = EpollEvent { events, data: fd as u64 };
+    check(unsafe { epoll_ctl(self.fd.as_raw_fd(), op, fd, &mut event) }).map(|_| ())
+  }
+}
+
+pub struct Events {
+  list: Vec<EpollEvent>,
+}
+
+impl Events {
+  pub fn with_capacity(capacity: usize) -> Self {
+    Self { list: Vec::with_capacity(capacity) }
+  }
+
+  pub fn iter(&self) -> impl Iterator<Item = Event> + '_ {
+    self.list.iter().map(|event| Event { events: event.events, fd: event.data as RawFd })
+  }
+}
+
+pub struct Event {
+  events: u32,
+  fd: RawFd,
+}
+
+impl Event {
+  pub fn fd(&self) -> RawFd {
+    self.fd
+  }
+
+  pub fn is_readable(&self) -> bool {
+    self.events & EPOLLIN != 0
+  }
+
+  pub fn is_writable(&self) -> bool {
+    self.events & EPOLLOUT != 0
+  }
+
+  // The peer hung up or the socket is in an error state.
+  pub fn is_closed(&self) -> bool {
+    self.events & (EPOLLERR | EPOLLHUP | EPOLLRDHUP) != 0
+  }
+}
+
+fn check(result: i32) -> IoResult<i32> {
+  if result < 0 {
+    Err(Error::last_os_error())
+  } else {
+    Ok(result)
+  }
+}
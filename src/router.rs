@@ -0,0 +1,198 @@
+use crate::http::{Method, Request, Response, StatusCode};
+use super::server::Handler;
+
+// Dispatches requests to handlers by method and path pattern.
+//
+// Patterns are made of segments separated by '/':
+// - "users" only matches that exact segment,
+// - ":id" matches any single segment and makes it available as request.param("id"),
+// - "*rest" matches everything that is left of the path, so it can only come last.
+//
+// When more than one route matches, the most specific one wins: going segment by
+// segment, a static segment beats a parameter and a parameter beats a wildcard.
+// Routes that are equally specific are tried in the order they were added.
+pub struct Router {
+  routes: Vec<Route>,
+  fallback: Option<Box<dyn Handler>>
+}
+
+struct Route {
+  method: Method,
+  segments: Vec<Segment>,
+  handler: Box<dyn Handler>
+}
+
+enum Segment {
+  Static(String),
+  Param(String),
+  Wildcard(String)
+}
+
+impl Router {
+  pub fn new() -> Self {
+    Self { routes: Vec::new(), fallback: None }
+  }
+
+  pub fn route(mut self, method: Method, pattern: &str, handler: impl Handler + 'static) -> Self {
+    let segments: Vec<Segment> = split_path(pattern)
+      .map(|segment| {
+        if let Some(name) = segment.strip_prefix(':') {
+          Segment::Param(name.to_string())
+        } else if let Some(name) = segment.strip_prefix('*') {
+          Segment::Wildcard(name.to_string())
+        } else {
+          Segment::Static(segment.to_string())
+        }
+      })
+      .collect();
+
+    if let Some(i) = segments.iter().position(|s| matches!(s, Segment::Wildcard(_))) {
+      assert!(i == segments.len() - 1, "wildcard has to be the last segment of {}", pattern);
+    }
+
+    self.routes.push(Route { method, segments, handler: Box::new(handler) });
+    self
+  }
+
+  pub fn get(self, pattern: &str, handler: impl Handler + 'static) -> Self {
+    self.route(Method::GET, pattern, handler)
+  }
+
+  pub fn post(self, pattern: &str, handler: impl Handler + 'static) -> Self {
+    self.route(Method::POST, pattern, handler)
+  }
+
+  pub fn put(self, pattern: &str, handler: impl Handler + 'static) -> Self {
+    self.route(Method::PUT, pattern, handler)
+  }
+
+  pub fn patch(self, pattern: &str, handler: impl Handler + 'static) -> Self {
+    self.route(Method::PATCH, pattern, handler)
+  }
+
+  pub fn delete(self, pattern: &str, handler: impl Handler + 'static) -> Self {
+    self.route(Method::DELETE, pattern, handler)
+  }
+
+  // Handles the requests no route matches, for example a WebsiteHandler
+  // serving the files in public/. Without one those requests get a 404.
+  pub fn fallback(mut self, handler: impl Handler + 'static) -> Self {
+    self.fallback = Some(Box::new(handler));
+    self
+  }
+}
+
+impl Default for Router {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl Segment {
+  // Lower is more specific.
+  fn precedence(&self) -> u8 {
+    match self {
+      Self::Static(_) => 0,
+      Self::Param(_) => 1,
+      Self::Wildcard(_) => 2
+    }
+  }
+}
+
+impl Route {
+  fn precedence(&self) -> Vec<u8> {
+    self.segments.iter().map(Segment::precedence).collect()
+  }
+
+  // Returns the path parameters if the path matches the pattern.
+  fn matches(&self, path: &str) -> Option<Vec<(String, String)>> {
+    let mut params = Vec::new();
+    let mut parts = split_path(path);
+
+    for segment in &self.segments {
+      match segment {
+        Segment::Static(expected) => {
+          if parts.next()? != expected {
+            return None;
+          }
+        },
+        Segment::Param(name) => {
+          let value = parts.next().filter(|value| !value.is_empty())?;
+          params.push((name.clone(), value.to_string()));
+        },
+        Segment::Wildcard(name) => {
+          let rest: Vec<&str> = parts.by_ref().collect();
+          params.push((name.clone(), rest.join("/")));
+        }
+      }
+    }
+
+    if parts.next().is_some() {
+      return None;
+    }
+    Some(params)
+  }
+}
+
+fn split_path(path: &str) -> impl Iterator<Item = &str> {
+  let path = path.strip_prefix('/').unwrap_or(path);
+  // "/" has no segments at all, not a single empty one.
+  path.split('/').filter(move |_| !path.is_empty())
+}
+
+impl Handler for Router {
+  fn handle_request(&self, request: &Request) -> Response {
+    let path = request.path();
+    let mut best: Option<(&Route, Vec<(String, String)>)> = None;
+    let mut allowed: Vec<Method> = Vec::new();
+
+    for route in &self.routes {
+      let params = match route.matches(path) {
+        Some(params) => params,
+        None => continue
+      };
+
+      if route.method != *request.method() {
+        if !allowed.contains(&route.method) {
+          allowed.push(route.method);
+        }
+        continue;
+      }
+
+      if best.as_ref().is_none_or(|(current, _)| route.precedence() < current.precedence()) {
+        best = Some((route, params));
+      }
+    }
+
+    if let Some((route, params)) = best {
+      return route.handler.handle_request(&request.with_params(params));
+    }
+
+    // The path exists, just not for this method.
+    if !allowed.is_empty() {
+      // The server answers HEAD for every GET route.
+      if let Some(get) = allowed.iter().position(|method| *method == Method::GET) {
+        if !allowed.contains(&Method::HEAD) {
+          allowed.insert(get + 1, Method::HEAD);
+        }
+      }
+      // OPTIONS asks which methods there are, it gets the list without an error.
+      let status_code = match request.method() {
+        Method::OPTIONS => {
+          allowed.push(Method::OPTIONS);
+          StatusCode::NoContent
+        },
+        _ => StatusCode::MethodNotAllowed
+      };
+      let allow: Vec<&str> = allowed.iter().map(|method| method.as_str()).collect();
+      let mut response = Response::new(status_code, None);
+      response.set_header("Allow", &allow.join(", "));
+      return response;
+    }
+
+    match &self.fallback {
+      Some(handler) => handler.handle_request(request),
+      None => Response::new(StatusCode::NotFound, None)
+    }
+  }
+}
@@ -0,0 +1,70 @@
+// Sends files to sockets with sendfile(2), the kernel copies the file straight
+// from the page cache to the socket, without it ever passing through our memory.
+use crate::http::Transmit;
+use crate::http::response::copy_file;
+use std::fs::File;
+use std::io::{Error, ErrorKind, Result as IoResult};
+use std::net::TcpStream;
+
+// Sends up to count bytes of the file from offset on to the socket, and moves
+// offset past what was sent. Returns 0 when the file ends before offset.
+#[cfg(all(target_os = "linux", target_pointer_width = "64"))]
+pub fn send(socket: &TcpStream, file: &File, offset: &mut u64, count: u64) -> IoResult<usize> {
+  use std::os::fd::AsRawFd;
+
+  // off_t is 64 bits on 64-bit Linux.
+  extern "C" {
+    fn sendfile(out_fd: i32, in_fd: i32, offset: *mut i64, count: usize) -> isize;
+  }
+
+  let mut position = *offset as i64;
+  // Linux never sends more than this in one call anyway.
+  let count = count.min(0x7fff_f000) as usize;
+  let sent = unsafe { sendfile(socket.as_raw_fd(), file.as_raw_fd(), &mut position, count) };
+  if sent < 0 {
+    return Err(Error::last_os_error());
+  }
+
+  *offset = position as u64;
+  Ok(sent as usize)
+}
+
+// Elsewhere the file is read into a buffer and written from there.
+#[cfg(not(all(target_os = "linux", target_pointer_width = "64")))]
+pub fn send(mut socket: &TcpStream, mut file: &File, offset: &mut u64, count: u64) -> IoResult<usize> {
+  use std::io::{Read, Seek, SeekFrom, Write};
+
+  let mut buffer = [0; 64 * 1024];
+  let count = count.min(buffer.len() as u64) as usize;
+  file.seek(SeekFrom::Start(*offset))?;
+  let read = file.read(&mut buffer[..count])?;
+  if read == 0 {
+    return Ok(0);
+  }
+
+  let sent = socket.write(&buffer[..read])?;
+  *offset += sent as u64;
+  Ok(sent)
+}
+
+// EINVAL and ENOSYS, files on some file systems can't be sent with sendfile.
+pub fn is_unsupported(e: &Error) -> bool {
+  matches!(e.raw_os_error(), Some(22) | Some(38))
+}
+
+impl Transmit for TcpStream {
+  fn send_file(&mut self, file: &mut File, mut offset: u64, len: u64) -> IoResult<()> {
+    let end = offset + len;
+    while offset < end {
+      let remaining = end - offset;
+      match send(self, file, &mut offset, remaining) {
+        Ok(0) => return Err(ErrorKind::UnexpectedEof.into()),
+        Ok(_) => {},
+        Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+        Err(e) if is_unsupported(&e) => return copy_file(self, file, offset, remaining),
+        Err(e) => return Err(e)
+      }
+    }
+    Ok(())
+  }
+}
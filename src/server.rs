@@ -1,17 +1,50 @@
-use std::{net::TcpListener, io::{Write, Read}, convert::TryFrom};
-use crate::http::{Request, Response, StatusCode, response, ParseError};
+use std::{net::{TcpListener, TcpStream}, io::{Read, ErrorKind, Result as IoResult}, convert::TryFrom, time::Duration};
+use std::{mem, sync::Arc};
+use crate::http::{Method, Request, Response, ParseError, request::{PendingRequest, MAX_REQUEST_SIZE}};
+use crate::middleware::{Chain, Middleware};
+use crate::thread_pool::ThreadPool;
+#[cfg(target_os = "linux")]
+use crate::event_loop;
 
-pub trait Handler {
-  fn handle_request(&mut self, request: &Request) -> Response;
+// Handlers are shared by all worker threads, so they only get &self.
+// A handler that needs to change its state has to use a Mutex or atomics for it.
+pub trait Handler: Send + Sync {
+  fn handle_request(&self, request: &Request) -> Response;
 
-  fn handle_bad_request(&mut self, e: &ParseError) -> Response {
+  fn handle_bad_request(&self, e: &ParseError) -> Response {
     println!("Failed to parse request: {}", e);
-    Response::new(StatusCode::BadRequest, None)
+    Response::new(e.status_code(), None)
+  }
+}
+
+// How connections are spread over threads.
+pub enum Mode {
+  // Every connection is handed to a worker thread that blocks on it until it closes.
+  ThreadPool,
+  // Every thread runs an epoll event loop that serves many connections at once,
+  // which suits lots of idle keep-alive connections.
+  #[cfg(target_os = "linux")]
+  EventLoop,
+}
+
+// Plain functions and closures can be used as handlers too, which is handy for routes.
+impl<F> Handler for F
+where
+  F: Fn(&Request) -> Response + Send + Sync,
+{
+  fn handle_request(&self, request: &Request) -> Response {
+    self(request)
   }
 }
 
 pub struct Server {
   addr: String,
+  pub(crate) idle_timeout: Duration,
+  max_requests_per_connection: usize,
+  pub(crate) workers: usize,
+  queue_capacity: usize,
+  mode: Mode,
+  middleware: Vec<Box<dyn Middleware>>,
 }
 
 impl Server {
@@ -20,38 +53,77 @@ impl Server {
   // It does not accept 'self' as its first parameter
   pub fn new(addr: String) -> Self {
       Self {
-          addr
+          addr,
+          idle_timeout: Duration::from_secs(5),
+          max_requests_per_connection: 100,
+          workers: 4,
+          queue_capacity: 64,
+          mode: Mode::ThreadPool,
+          middleware: Vec::new(),
       }
   }
 
-  pub fn run(self, mut handler: impl Handler) {
+  // Adds middleware around the handler passed to run(). Middleware runs in the
+  // order it was added, the first one sees the request first and the response last.
+  pub fn middleware(mut self, middleware: impl Middleware + 'static) -> Self {
+    self.middleware.push(Box::new(middleware));
+    self
+  }
+
+  pub fn mode(mut self, mode: Mode) -> Self {
+    self.mode = mode;
+    self
+  }
+
+  // Number of worker threads, or of event loop threads in Mode::EventLoop.
+  pub fn workers(mut self, workers: usize) -> Self {
+    self.workers = workers;
+    self
+  }
+
+  // Accepted connections waiting for a free worker. When the queue is full
+  // the server stops accepting until a worker picks up the next connection.
+  pub fn queue_capacity(mut self, capacity: usize) -> Self {
+    self.queue_capacity = capacity;
+    self
+  }
+
+  // How long a persistent connection may wait for its next request before it is closed.
+  pub fn idle_timeout(mut self, timeout: Duration) -> Self {
+    self.idle_timeout = timeout;
+    self
+  }
+
+  // The connection is closed after this many requests, even if the client wants to keep it.
+  pub fn max_requests_per_connection(mut self, max: usize) -> Self {
+    self.max_requests_per_connection = max;
+    self
+  }
+
+  pub fn run(mut self, handler: impl Handler + 'static) {
     let listener = TcpListener::bind(&self.addr).unwrap();
-    println!("Server is running on {}", self.addr);
+    println!("Server is running on {} with {} workers", self.addr, self.workers);
+
+    let handler = Chain::new(mem::take(&mut self.middleware), handler);
+
+    match self.mode {
+      Mode::ThreadPool => self.run_thread_pool(listener, handler),
+      #[cfg(target_os = "linux")]
+      Mode::EventLoop => event_loop::run(self, listener, handler),
+    }
+  }
+
+  fn run_thread_pool(self, listener: TcpListener, handler: impl Handler + 'static) {
+    let pool = ThreadPool::new(self.workers, self.queue_capacity);
+    let server = Arc::new(self);
+    let handler = Arc::new(handler);
 
     loop {
       match listener.accept() {
-        Ok((mut stream, _)) => {
-          let mut buffer = [0; 1024];
-          match stream.read(&mut buffer) {
-            Ok(_) => {
-              println!("Recieved a request: {}", String::from_utf8_lossy(&buffer));
-              let response = match Request::try_from(&buffer[..]) {
-                Ok(request) => {
-                  handler.handle_request(&request)
-                },
-                Err(err) => {
-                  handler.handle_bad_request(&err)
-                }
-              };
-
-              if let Err(e) = response.send(&mut stream) {
-                println!("Failed to send response: {}", e);
-              }
-            },
-            Err(e) => {
-              println!("Failed to read from connection: {}", e);
-            }
-          }
+        Ok((stream, _)) => {
+          let server = Arc::clone(&server);
+          let handler = Arc::clone(&handler);
+          pool.execute(move || server.handle_connection(stream, &*handler));
         },
         Err(err) => {
           println!("Failed to establish connection {}", err);
@@ -60,4 +132,120 @@ impl Server {
       }
     }
   }
-}
\ No newline at end of file
+
+  // HTTP/1.1 connections are persistent by default, so we keep reading requests
+  // from the same stream until the client asks to close it, goes idle or hits the
+  // request limit. Pipelined requests are already in the buffer and are answered
+  // in the order they were sent.
+  fn handle_connection(&self, mut stream: TcpStream, handler: &impl Handler) {
+    if let Err(e) = stream.set_read_timeout(Some(self.idle_timeout)) {
+      println!("Failed to set read timeout: {}", e);
+      return;
+    }
+    // A client that stops reading a large response would hold the worker just
+    // the same as one that stops sending.
+    if let Err(e) = stream.set_write_timeout(Some(self.idle_timeout)) {
+      println!("Failed to set write timeout: {}", e);
+      return;
+    }
+
+    let mut buffer = Vec::new();
+    let mut pending = PendingRequest::new();
+    let mut served = 0;
+
+    loop {
+      let parsed = match read_request(&mut stream, &mut buffer, &mut pending) {
+        Ok(Some(parsed)) => parsed,
+        Ok(None) => return,
+        Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => return,
+        Err(e) => {
+          println!("Failed to read from connection: {}", e);
+          return;
+        }
+      };
+      served += 1;
+
+      let (mut response, length) = self.respond(&buffer, parsed, served, handler);
+
+      if let Err(e) = response.send(&mut stream) {
+        println!("Failed to send response: {}", e);
+        return;
+      }
+
+      if !response.keep_alive() {
+        return;
+      }
+      buffer.drain(..length);
+      pending.reset();
+    }
+  }
+
+  // A HEAD response has the same headers as the GET response would, so HEAD is
+  // answered by the GET handler and the body is left out when sending.
+  fn handle(&self, request: &Request, handler: &impl Handler) -> Response {
+    if *request.method() != Method::HEAD {
+      return handler.handle_request(request);
+    }
+
+    let mut response = handler.handle_request(&request.head_as_get());
+    response.set_omit_body(true);
+    response
+  }
+
+  // Answers the request at the start of the buffer and decides whether the
+  // connection stays open after it. Returns the response and how many bytes
+  // of the buffer the request took.
+  pub(crate) fn respond(&self, buffer: &[u8], parsed: Result<usize, ParseError>, served: usize, handler: &impl Handler) -> (Response, usize) {
+    // After a bad request we can't tell where the next one starts,
+    // so the connection is always closed.
+    let (mut response, keep_alive, length) = match parsed {
+      Ok(length) => {
+        match Request::try_from(&buffer[..length]) {
+          Ok(request) => {
+            // Only the request line, bodies can be large and headers can hold
+            // cookies and credentials. The target is escaped, it comes from the client.
+            println!("Received a request: {} {}", request.method(), request.target().escape_debug());
+            let keep_alive = !request.headers().has_token("Connection", "close");
+            (self.handle(&request, handler), keep_alive, length)
+          },
+          Err(err) => (handler.handle_bad_request(&err), false, length)
+        }
+      },
+      Err(err) => (handler.handle_bad_request(&err), false, 0)
+    };
+
+    response.set_keep_alive(keep_alive && served < self.max_requests_per_connection);
+    (response, length)
+  }
+}
+
+// Returns the length of the complete request at the start of the buffer, the parse
+// error if the bytes read can never become a valid request, or None if more bytes
+// have to be read first.
+pub(crate) fn next_request(buffer: &[u8], pending: &mut PendingRequest) -> Option<Result<usize, ParseError>> {
+  match pending.length(buffer) {
+    Ok(Some(length)) => Some(Ok(length)),
+    Ok(None) if buffer.len() >= MAX_REQUEST_SIZE => Some(Err(ParseError::RequestTooLarge)),
+    Ok(None) => None,
+    Err(e) => Some(Err(e))
+  }
+}
+
+// Reads from the stream into the growable buffer until it holds a complete request,
+// the head up to the empty line followed by its body.
+// Returns the result of next_request(), or None if the client closed the connection first.
+fn read_request(stream: &mut TcpStream, buffer: &mut Vec<u8>, pending: &mut PendingRequest) -> IoResult<Option<Result<usize, ParseError>>> {
+  let mut chunk = [0; 1024];
+
+  loop {
+    if let Some(parsed) = next_request(buffer, pending) {
+      return Ok(Some(parsed));
+    }
+
+    let n = stream.read(&mut chunk)?;
+    if n == 0 {
+      return Ok(None);
+    }
+    buffer.extend_from_slice(&chunk[..n]);
+  }
+}
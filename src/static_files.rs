@@ -0,0 +1,356 @@
+use std::fs;
+use std::io::{ErrorKind, Result as IoResult};
+use std::path::{Path, PathBuf};
+
+// What to do when a path goes through a symbolic link.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SymlinkPolicy {
+  // Never follow symbolic links.
+  Deny,
+  // Follow links whose target is inside the root.
+  WithinRoot,
+  // Follow every link, even out of the root. Only for roots where nobody
+  // untrusted can create links.
+  Follow,
+}
+
+// What to do with files and directories whose name starts with a dot, like .git or .env.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DotfilePolicy {
+  // Answer as if they didn't exist.
+  Ignore,
+  // Refuse them with 403 Forbidden.
+  Deny,
+  // Serve them like any other file.
+  Allow,
+}
+
+#[derive(Debug)]
+pub enum ResolveError {
+  NotFound,
+  Forbidden,
+}
+
+// Maps request paths to files under a root directory.
+//
+// The root is canonicalized once when the StaticFiles is created. Request paths are
+// then resolved one component at a time, checking every step against the symlink
+// and dotfile policies, instead of joining strings and comparing prefixes afterwards.
+// Nothing outside of the root is ever returned.
+#[derive(Debug)]
+pub struct StaticFiles {
+  root: PathBuf,
+  symlinks: SymlinkPolicy,
+  dotfiles: DotfilePolicy,
+}
+
+impl StaticFiles {
+  pub fn new(root: impl AsRef<Path>) -> IoResult<Self> {
+    let root = fs::canonicalize(root)?;
+    if !root.is_dir() {
+      return Err(ErrorKind::NotADirectory.into());
+    }
+
+    Ok(Self { root, symlinks: SymlinkPolicy::WithinRoot, dotfiles: DotfilePolicy::Ignore })
+  }
+
+  pub fn symlinks(mut self, policy: SymlinkPolicy) -> Self {
+    self.symlinks = policy;
+    self
+  }
+
+  pub fn dotfiles(mut self, policy: DotfilePolicy) -> Self {
+    self.dotfiles = policy;
+    self
+  }
+
+  pub fn root(&self) -> &Path {
+    &self.root
+  }
+
+  // Whether names starting with a dot may be shown to clients, in directory listings for example.
+  pub fn shows_dotfiles(&self) -> bool {
+    self.dotfiles == DotfilePolicy::Allow
+  }
+
+  // Resolves a request path like "/css/style.css" to the file or directory it names.
+  pub fn resolve(&self, request_path: &str) -> Result<PathBuf, ResolveError> {
+    let mut path = self.root.clone();
+
+    for segment in request_path.split('/').filter(|segment| !segment.is_empty()) {
+      // The request path is already normalized, these can only come from a bug
+      // elsewhere, but they must never reach the file system. A backslash is a
+      // separator on Windows.
+      if segment == "." || segment == ".." || segment.contains(['\\', '\0']) {
+        return Err(ResolveError::NotFound);
+      }
+
+      if segment.starts_with('.') {
+        match self.dotfiles {
+          DotfilePolicy::Ignore => return Err(ResolveError::NotFound),
+          DotfilePolicy::Deny => return Err(ResolveError::Forbidden),
+          DotfilePolicy::Allow => {},
+        }
+      }
+
+      // Only directories can have children.
+      if !path.is_dir() {
+        return Err(ResolveError::NotFound);
+      }
+      path.push(segment);
+
+      let metadata = fs::symlink_metadata(&path).map_err(|_| ResolveError::NotFound)?;
+      if metadata.file_type().is_symlink() {
+        path = self.follow(&path)?;
+      }
+    }
+
+    Ok(path)
+  }
+
+  fn follow(&self, link: &Path) -> Result<PathBuf, ResolveError> {
+    if self.symlinks == SymlinkPolicy::Deny {
+      println!("Refused to follow symbolic link: {}", link.display());
+      return Err(ResolveError::Forbidden);
+    }
+
+    let target = fs::canonicalize(link).map_err(|_| ResolveError::NotFound)?;
+
+    // Path::starts_with compares whole components, so /public-old doesn't count as inside /public.
+    if self.symlinks == SymlinkPolicy::WithinRoot && !target.starts_with(&self.root) {
+      println!("Refused to follow symbolic link out of the root: {}", link.display());
+      return Err(ResolveError::Forbidden);
+    }
+
+    Ok(target)
+  }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+  use super::*;
+  use crate::http::path;
+  use std::os::unix::fs::symlink;
+  use std::process;
+  use std::sync::atomic::{AtomicUsize, Ordering};
+
+  // A directory with a public root and a secret file next to it, removed again
+  // when the test is done:
+  //
+  // public/index.html
+  // public/.env
+  // public/.git/config
+  // public/sub/in.txt
+  // public/link_in -> public/index.html
+  // public/link_out -> secret.txt
+  // public/sub/dir_out -> outside/
+  // secret.txt
+  // outside/file.txt
+  struct TempRoot {
+    dir: PathBuf
+  }
+
+  impl TempRoot {
+    fn new() -> Self {
+      static COUNT: AtomicUsize = AtomicUsize::new(0);
+      let name = format!("rs_server_static_files_{}_{}", process::id(), COUNT.fetch_add(1, Ordering::SeqCst));
+      let dir = fs::canonicalize(std::env::temp_dir()).unwrap().join(name);
+
+      let public = dir.join("public");
+      fs::create_dir_all(public.join(".git")).unwrap();
+      fs::create_dir_all(public.join("sub")).unwrap();
+      fs::create_dir_all(dir.join("outside")).unwrap();
+      fs::write(public.join("index.html"), "index").unwrap();
+      fs::write(public.join(".env"), "SECRET=1").unwrap();
+      fs::write(public.join(".git/config"), "[core]").unwrap();
+      fs::write(public.join("sub/in.txt"), "in").unwrap();
+      fs::write(dir.join("secret.txt"), "secret").unwrap();
+      fs::write(dir.join("outside/file.txt"), "outside").unwrap();
+      symlink(public.join("index.html"), public.join("link_in")).unwrap();
+      symlink(dir.join("secret.txt"), public.join("link_out")).unwrap();
+      symlink(dir.join("outside"), public.join("sub/dir_out")).unwrap();
+
+      Self { dir }
+    }
+
+    fn public(&self) -> PathBuf {
+      self.dir.join("public")
+    }
+
+    fn files(&self) -> StaticFiles {
+      StaticFiles::new(self.public()).unwrap()
+    }
+  }
+
+  impl Drop for TempRoot {
+    fn drop(&mut self) {
+      let _ = fs::remove_dir_all(&self.dir);
+    }
+  }
+
+  // Goes through the same steps as a request: normalizing the raw path, then resolving it.
+  fn resolve_raw(files: &StaticFiles, raw: &str) -> Result<PathBuf, ResolveError> {
+    let normalized = path::normalize(raw).map_err(|_| ResolveError::NotFound)?;
+    files.resolve(&normalized)
+  }
+
+  fn is_not_found(result: Result<PathBuf, ResolveError>) -> bool {
+    matches!(result, Err(ResolveError::NotFound))
+  }
+
+  fn is_forbidden(result: Result<PathBuf, ResolveError>) -> bool {
+    matches!(result, Err(ResolveError::Forbidden))
+  }
+
+  #[test]
+  fn resolves_files_and_directories() {
+    let root = TempRoot::new();
+    let files = root.files();
+
+    assert_eq!(files.resolve("/index.html").unwrap(), root.public().join("index.html"));
+    assert_eq!(files.resolve("/sub/in.txt").unwrap(), root.public().join("sub/in.txt"));
+    assert_eq!(files.resolve("/sub/").unwrap(), root.public().join("sub"));
+    assert_eq!(files.resolve("/").unwrap(), root.public());
+    assert!(is_not_found(files.resolve("/missing.html")));
+    assert!(is_not_found(files.resolve("/index.html/child")));
+  }
+
+  #[test]
+  fn traversal_payloads_stay_in_the_root() {
+    let root = TempRoot::new();
+    let files = root.files();
+
+    let payloads = [
+      "/../secret.txt",
+      "/../../../../../../etc/passwd",
+      "/sub/../../secret.txt",
+      "/%2e%2e/secret.txt",
+      "/%2E%2E/%2e%2e/secret.txt",
+      "/..%2fsecret.txt",
+      "/sub%2f..%2f..%2fsecret.txt",
+      "/..\\secret.txt",
+      "/sub\\..\\..\\secret.txt",
+      "/%5c..%5csecret.txt",
+      "/index.html%00",
+      "/%00/../secret.txt",
+      "/secret.txt%00.html",
+    ];
+
+    for payload in payloads {
+      let result = resolve_raw(&files, payload);
+      assert!(is_not_found(result), "{} wasn't refused", payload);
+    }
+  }
+
+  #[test]
+  fn refuses_dot_segments_that_skipped_normalizing() {
+    let root = TempRoot::new();
+    let files = root.files();
+
+    assert!(is_not_found(files.resolve("/../secret.txt")));
+    assert!(is_not_found(files.resolve("/sub/../../secret.txt")));
+    assert!(is_not_found(files.resolve("/./index.html")));
+    assert!(is_not_found(files.resolve("/..\\secret.txt")));
+    assert!(is_not_found(files.resolve("/index.html\0")));
+  }
+
+  #[test]
+  fn symlinks_are_never_followed_with_deny() {
+    let root = TempRoot::new();
+    let files = root.files().symlinks(SymlinkPolicy::Deny);
+
+    assert!(is_forbidden(files.resolve("/link_in")));
+    assert!(is_forbidden(files.resolve("/link_out")));
+    assert!(is_forbidden(files.resolve("/sub/dir_out/file.txt")));
+    assert!(files.resolve("/index.html").is_ok());
+  }
+
+  #[test]
+  fn symlinks_within_the_root_are_followed_by_default() {
+    let root = TempRoot::new();
+    let files = root.files();
+
+    assert_eq!(files.resolve("/link_in").unwrap(), root.public().join("index.html"));
+    assert!(is_forbidden(files.resolve("/link_out")));
+    assert!(is_forbidden(files.resolve("/sub/dir_out/file.txt")));
+    assert!(is_forbidden(files.resolve("/sub/dir_out/")));
+  }
+
+  #[test]
+  fn every_symlink_is_followed_with_follow() {
+    let root = TempRoot::new();
+    let files = root.files().symlinks(SymlinkPolicy::Follow);
+
+    assert_eq!(files.resolve("/link_in").unwrap(), root.public().join("index.html"));
+    assert_eq!(files.resolve("/link_out").unwrap(), root.dir.join("secret.txt"));
+    assert_eq!(files.resolve("/sub/dir_out/file.txt").unwrap(), root.dir.join("outside/file.txt"));
+  }
+
+  #[test]
+  fn dotfiles_are_hidden_by_default() {
+    let root = TempRoot::new();
+    let files = root.files();
+
+    assert!(is_not_found(files.resolve("/.env")));
+    assert!(is_not_found(files.resolve("/.git/config")));
+    assert!(is_not_found(files.resolve("/.git/")));
+    assert!(!files.shows_dotfiles());
+  }
+
+  #[test]
+  fn dotfiles_are_refused_with_deny() {
+    let root = TempRoot::new();
+    let files = root.files().dotfiles(DotfilePolicy::Deny);
+
+    assert!(is_forbidden(files.resolve("/.env")));
+    assert!(is_forbidden(files.resolve("/.git/config")));
+    assert!(!files.shows_dotfiles());
+  }
+
+  #[test]
+  fn dotfiles_are_served_with_allow() {
+    let root = TempRoot::new();
+    let files = root.files().dotfiles(DotfilePolicy::Allow);
+
+    assert_eq!(files.resolve("/.env").unwrap(), root.public().join(".env"));
+    assert_eq!(files.resolve("/.git/config").unwrap(), root.public().join(".git/config"));
+    assert!(files.shows_dotfiles());
+  }
+
+  #[test]
+  fn accepts_a_relative_root() {
+    let root = TempRoot::new();
+
+    // The same directory, reached from the working directory with "..".
+    let cwd = std::env::current_dir().unwrap();
+    let mut relative = PathBuf::new();
+    for _ in cwd.components().skip(1) {
+      relative.push("..");
+    }
+    relative.push(root.public().strip_prefix("/").unwrap());
+    assert!(relative.is_relative());
+
+    let files = StaticFiles::new(&relative).unwrap();
+    assert_eq!(files.root(), root.public());
+    assert_eq!(files.resolve("/index.html").unwrap(), root.public().join("index.html"));
+    assert!(is_not_found(resolve_raw(&files, "/../secret.txt")));
+  }
+
+  #[test]
+  fn accepts_a_root_with_a_trailing_slash() {
+    let root = TempRoot::new();
+
+    let files = StaticFiles::new(format!("{}/", root.public().display())).unwrap();
+    assert_eq!(files.root(), root.public());
+    assert_eq!(files.resolve("/sub/in.txt").unwrap(), root.public().join("sub/in.txt"));
+    assert!(is_not_found(resolve_raw(&files, "/%2e%2e/secret.txt")));
+  }
+
+  #[test]
+  fn refuses_a_root_that_is_not_a_directory() {
+    let root = TempRoot::new();
+
+    assert!(StaticFiles::new(root.public().join("index.html")).is_err());
+    assert!(StaticFiles::new(root.dir.join("missing")).is_err());
+  }
+}
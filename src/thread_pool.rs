@@ -0,0 +1,86 @@
+use std::sync::{mpsc::{self, Receiver, SyncSender}, Arc, Mutex};
+use std::panic::{self, AssertUnwindSafe};
+use std::thread::{self, JoinHandle};
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+// A fixed number of worker threads that take jobs from a shared queue.
+// The queue is bounded, once it is full execute() blocks until a worker is free,
+// so a flood of connections slows down the accept loop instead of piling up in memory.
+pub struct ThreadPool {
+  workers: Vec<Worker>,
+  sender: Option<SyncSender<Job>>,
+}
+
+impl ThreadPool {
+  pub fn new(size: usize, queue_capacity: usize) -> Self {
+    assert!(size > 0, "thread pool needs at least one worker");
+
+    let (sender, receiver) = mpsc::sync_channel(queue_capacity);
+    // The receiving end of a channel can't be shared, so workers take turns
+    // locking it to pick the next job.
+    let receiver = Arc::new(Mutex::new(receiver));
+
+    let workers = (0..size)
+      .map(|id| Worker::new(id, Arc::clone(&receiver)))
+      .collect();
+
+    Self { workers, sender: Some(sender) }
+  }
+
+  pub fn execute<F>(&self, job: F)
+  where
+    F: FnOnce() + Send + 'static,
+  {
+    if let Some(sender) = &self.sender {
+      if sender.send(Box::new(job)).is_err() {
+        println!("Failed to queue job, all workers have stopped");
+      }
+    }
+  }
+}
+
+impl Drop for ThreadPool {
+  // Closing the channel makes every worker leave its loop once the queue is empty.
+  fn drop(&mut self) {
+    drop(self.sender.take());
+
+    for worker in &mut self.workers {
+      if let Some(thread) = worker.thread.take() {
+        if thread.join().is_err() {
+          println!("Worker {} panicked", worker.id);
+        }
+      }
+    }
+  }
+}
+
+struct Worker {
+  id: usize,
+  thread: Option<JoinHandle<()>>,
+}
+
+impl Worker {
+  fn new(id: usize, receiver: Arc<Mutex<Receiver<Job>>>) -> Self {
+    let thread = thread::spawn(move || loop {
+      // The lock is released at the end of this statement, before the job runs.
+      let job = match receiver.lock() {
+        Ok(receiver) => receiver.recv(),
+        Err(_) => break,
+      };
+
+      match job {
+        // A panicking job would otherwise take the worker down with it
+        // and the pool would slowly run out of threads.
+        Ok(job) => {
+          if panic::catch_unwind(AssertUnwindSafe(job)).is_err() {
+            println!("Worker {} recovered from a panicked job", id);
+          }
+        },
+        Err(_) => break,
+      }
+    });
+
+    Self { id, thread: Some(thread) }
+  }
+}
@@ -1,48 +1,333 @@
-use crate::http::{Request, Response, StatusCode, Method};
+use crate::http::{date, Request, Response, StatusCode, Method};
+use crate::http::conditional::{Precondition, Validators};
+use crate::http::{negotiate, percent};
+use crate::http::range::{self, ByteRanges};
 use super::server::Handler;
-use std::fs;
+use super::autoindex;
+use super::mime::MimeTypes;
+use super::static_files::{DotfilePolicy, ResolveError, StaticFiles, SymlinkPolicy};
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+
+// The precompressed copies looked for next to a file, with the content coding
+// they are sent with, in the order they are preferred.
+const SIDECARS: [(&str, &str); 2] = [("br", "br"), ("gzip", "gz")];
+
 pub struct WebsiteHandler {
-  public_path: String
+  files: StaticFiles,
+  mime_types: MimeTypes,
+  autoindex: bool,
+  index_files: Vec<String>,
+  html_extension: bool,
+  precompressed: bool,
+  // The path prefixes left out of the single-page application fallback,
+  // None when it is off.
+  spa_fallback: Option<Vec<String>>
 }
 
 impl WebsiteHandler {
+  // The public path is resolved once here, so it can be relative or end with a slash.
   pub fn new(public_path: String) -> Self {
-    Self { public_path }
+    let files = match StaticFiles::new(&public_path) {
+      Ok(files) => files,
+      Err(e) => panic!("Failed to open public path {}: {}", public_path, e)
+    };
+
+    Self {
+      files,
+      mime_types: MimeTypes::new(),
+      autoindex: false,
+      index_files: vec!["index.html".to_string()],
+      html_extension: false,
+      precompressed: true,
+      spa_fallback: None
+    }
+  }
+
+  // Serves files with this extension as the given Content-Type,
+  // replacing the built-in type for it if there is one.
+  pub fn mime_type(mut self, extension: &str, mime_type: &str) -> Self {
+    self.mime_types.insert(extension, mime_type);
+    self
+  }
+
+  // Whether symbolic links inside the public path are followed, by default only
+  // links that stay inside it are.
+  pub fn symlinks(mut self, policy: SymlinkPolicy) -> Self {
+    self.files = self.files.symlinks(policy);
+    self
+  }
+
+  // Whether files like .env or .git/ are served, by default they are hidden.
+  pub fn dotfiles(mut self, policy: DotfilePolicy) -> Self {
+    self.files = self.files.dotfiles(policy);
+    self
   }
 
-  fn read_file(&self, file_path: &str) -> Option<String>{
-    let path = format!("{}/{}", self.public_path, file_path);
+  // Lists the contents of directories instead of answering 404 for them.
+  pub fn autoindex(mut self, enabled: bool) -> Self {
+    self.autoindex = enabled;
+    self
+  }
+
+  // The files served for a directory, the first one that exists wins.
+  // An empty list never serves an index file.
+  pub fn index_files(mut self, names: &[&str]) -> Self {
+    self.index_files = names.iter().map(|name| name.to_string()).collect();
+    self
+  }
+
+  // Serves about.html for /about when there is no file named "about".
+  pub fn html_extension(mut self, enabled: bool) -> Self {
+    self.html_extension = enabled;
+    self
+  }
 
-    match fs::canonicalize(path) {
-      Ok(path) => {
-        if path.starts_with(&self.public_path) {
-          fs::read_to_string(path).ok()
-        } else {
-          println!("Directory Traversal attack attempted: {}", file_path);
-          None
+  // Serves style.css.br or style.css.gz for style.css when the client accepts
+  // that encoding, so files compressed at build time aren't compressed again on
+  // every request.
+  pub fn precompressed(mut self, enabled: bool) -> Self {
+    self.precompressed = enabled;
+    self
+  }
+
+  // For single-page applications, which do their routing in the browser: a page
+  // like /users/42 that has no file gets the root index.html instead of a 404.
+  // Missing assets like /app.js, and paths under the excluded prefixes, an API
+  // at /api for example, still get a real 404.
+  pub fn spa_fallback(mut self, excluded_prefixes: &[&str]) -> Self {
+    self.spa_fallback = Some(excluded_prefixes.iter().map(|prefix| prefix.trim_end_matches('/').to_string()).collect());
+    self
+  }
+
+  fn serve(&self, request: &Request) -> Response {
+    let request_path = request.path();
+    match self.files.resolve(request_path) {
+      Ok(path) if path.is_dir() => self.serve_directory(request, &path),
+      Ok(path) => self.serve_file(request, &path),
+      Err(ResolveError::NotFound) => self.serve_missing(request),
+      Err(ResolveError::Forbidden) => Response::new(StatusCode::Forbidden, None)
+    }
+  }
+
+  // Nothing exists at the path, maybe something else should be served in its place.
+  fn serve_missing(&self, request: &Request) -> Response {
+    let request_path = request.path();
+    let has_extension = Path::new(request_path).extension().is_some();
+
+    if self.html_extension && !request_path.ends_with('/') && !has_extension {
+      if let Ok(path) = self.files.resolve(&format!("{}.html", request_path)) {
+        if path.is_file() {
+          return self.serve_file(request, &path);
+        }
+      }
+    }
+
+    let mut not_found = Response::new(StatusCode::NotFound, None);
+
+    if let Some(excluded_prefixes) = &self.spa_fallback {
+      let excluded = excluded_prefixes.iter().any(|prefix| is_under(request_path, prefix));
+      if !excluded {
+        // Whether this URL gets index.html or a 404 depends on Accept, a cache
+        // must not hand the 404 a script got to a browser navigating to the page.
+        not_found.add_vary("Accept");
+
+        if !has_extension && accepts_html(request) {
+          if let Some(index) = self.index_file("/") {
+            let mut response = self.serve_file(request, &index);
+            response.add_vary("Accept");
+            return response;
+          }
         }
-      },
-      Err(_) => {
-        None
       }
     }
+
+    not_found
+  }
+
+  fn serve_directory(&self, request: &Request, dir: &Path) -> Response {
+    // Relative links in /docs/index.html are resolved against /docs/, so the
+    // client has to ask for the directory with its trailing slash.
+    if !request.path().ends_with('/') {
+      let mut response = Response::new(StatusCode::MovedPermanently, None);
+      response.set_header("Location", &directory_location(request));
+      return response;
+    }
+
+    if let Some(index) = self.index_file(request.path()) {
+      return self.serve_file(request, &index);
+    }
+
+    if self.autoindex {
+      return autoindex::render(request, dir, self.files.shows_dotfiles());
+    }
+    Response::new(StatusCode::NotFound, None)
+  }
+
+  // Index files go through resolve() like any other path, an index.html that is
+  // a symbolic link out of the root isn't served either.
+  fn index_file(&self, dir: &str) -> Option<PathBuf> {
+    self.index_files
+      .iter()
+      .filter_map(|name| self.files.resolve(&format!("{}{}", dir, name)).ok())
+      .find(|path| path.is_file())
+  }
+
+  fn serve_file(&self, request: &Request, path: &Path) -> Response {
+    let sidecars = self.sidecars(path);
+    let codings: Vec<&str> = sidecars.iter().map(|(_, coding)| *coding).collect();
+    let accept_encoding = request.headers().get_all("Accept-Encoding").collect::<Vec<_>>().join(",");
+    let (file, encoding) = match negotiate::preferred_encoding(&accept_encoding, &codings) {
+      Some(coding) => match sidecars.iter().find(|(_, sidecar_coding)| *sidecar_coding == coding) {
+        Some((sidecar, _)) => (sidecar.as_path(), Some(coding)),
+        None => (path, None)
+      },
+      None => (path, None)
+    };
+
+    // The metadata comes from the open file, so the validators and the
+    // Content-Length describe the file that is sent, even if it is replaced meanwhile.
+    let (file, metadata) = match File::open(file).and_then(|file| file.metadata().map(|metadata| (file, metadata))) {
+      Ok((file, metadata)) if metadata.is_file() => (file, metadata),
+      _ => return Response::new(StatusCode::NotFound, None)
+    };
+
+    // Browsers revalidate their cached copy with If-None-Match / If-Modified-Since,
+    // when it is still fresh they get an empty 304 instead of the whole file.
+    let validators = Validators::from_metadata(&metadata);
+    let mut response = match validators.evaluate(request) {
+      Precondition::Proceed => self.file_response(request, &validators, file, metadata.len(), self.mime_types.get(path)),
+      Precondition::NotModified => {
+        // The Content-Type of the 200 it stands for, middleware like Compression
+        // needs it to give both the same ETag and Vary.
+        let mut response = Response::new(StatusCode::NotModified, None);
+        response.set_header("Content-Type", self.mime_types.get(path));
+        response
+      },
+      Precondition::Failed => return Response::new(StatusCode::PreconditionFailed, None)
+    };
+
+    response.set_header("ETag", &validators.etag);
+    response.set_header("Last-Modified", &date::format(validators.last_modified));
+    // The sidecar's ETag is its own, so caches keep the encodings apart.
+    if let Some(coding) = encoding {
+      response.set_header("Content-Encoding", coding);
+    }
+    if !sidecars.is_empty() {
+      response.add_vary("Accept-Encoding");
+    }
+    response
+  }
+
+  // The precompressed copies of the file that exist, as (path, content coding).
+  fn sidecars(&self, path: &Path) -> Vec<(PathBuf, &'static str)> {
+    if !self.precompressed {
+      return Vec::new();
+    }
+
+    SIDECARS.iter().filter_map(|(coding, extension)| {
+      let mut sidecar = path.as_os_str().to_owned();
+      sidecar.push(".");
+      sidecar.push(extension);
+      let sidecar = PathBuf::from(sidecar);
+
+      // The file itself went through resolve(), the sidecar didn't. Only regular
+      // files are served, a symbolic link could point anywhere.
+      match fs::symlink_metadata(&sidecar) {
+        Ok(metadata) if metadata.is_file() => Some((sidecar, *coding)),
+        _ => None
+      }
+    }).collect()
+  }
+
+  // The whole file, or only the parts the Range header asks for.
+  // Files are sent as they are, byte for byte, not every file in public/ is text.
+  fn file_response(&self, request: &Request, validators: &Validators, file: File, len: u64, mime_type: &str) -> Response {
+    // Range is only defined for GET, a HEAD request gets the headers of the whole file.
+    let ranges = match request.method() {
+      Method::GET if !request.is_head() => range::byte_ranges(request, validators, len),
+      _ => ByteRanges::Full
+    };
+
+    let mut response = match ranges {
+      ByteRanges::Full => {
+        let mut response = Response::file(StatusCode::Ok, file, 0, len);
+        response.set_header("Content-Type", mime_type);
+        response
+      },
+      ByteRanges::Unsatisfiable => {
+        let mut response = Response::new(StatusCode::RangeNotSatisfiable, None);
+        response.set_header("Content-Range", &format!("bytes */{}", len));
+        response
+      },
+      ByteRanges::Partial(ranges) if ranges.len() == 1 => {
+        let range = &ranges[0];
+        let mut response = Response::file(StatusCode::PartialContent, file, range.start, range.end - range.start);
+        response.set_header("Content-Type", mime_type);
+        response.set_header("Content-Range", &range::content_range(range, len));
+        response
+      },
+      ByteRanges::Partial(ranges) => {
+        // The ETag is unique to this version of the file, which makes it a good boundary.
+        let boundary = format!("rs_server_{}", validators.etag.trim_matches('"'));
+        let parts = range::multipart(len, &ranges, mime_type, &boundary);
+        let mut response = Response::parts(StatusCode::PartialContent, file, parts);
+        response.set_header("Content-Type", &format!("multipart/byteranges; boundary={}", boundary));
+        response
+      }
+    };
+
+    response.set_header("Accept-Ranges", "bytes");
+    response
   }
 }
 
 impl Handler for WebsiteHandler {
-  fn handle_request(&mut self, request: &Request) -> Response {
+  fn handle_request(&self, request: &Request) -> Response {
     match request.method() {
-      Method::GET => match request.path() {
-        "/" => Response::new(StatusCode::Ok, self.read_file("index.html")),
-        "/about" => Response::new(StatusCode::Ok, self.read_file("about.html")),
-        path => match self.read_file(path) {
-          Some(contents) => Response::new(StatusCode::Ok, Some(contents)),
-          None => Response::new(StatusCode::NotFound, None)
-        }
-
-      }
-      _ => Response::new(StatusCode::NotFound, Some("<h2>404</h2>".to_string()))
+      Method::GET => self.serve(request),
+      // Which methods the site answers, for a path or with "OPTIONS *" for the whole
+      // server. CORS preflights are answered by the Cors middleware before this.
+      Method::OPTIONS => {
+        let mut response = Response::new(StatusCode::NoContent, None);
+        response.set_header("Allow", "GET, HEAD, OPTIONS");
+        response
+      },
+      _ => Response::new(StatusCode::NotFound, Some("<h2>404</h2>".into()))
 
     }
   }
-}
\ No newline at end of file
+}
+// The directory's URL with a trailing slash, keeping the query string.
+// The path is encoded again since it was decoded when the request was parsed.
+fn directory_location(request: &Request) -> String {
+  let mut location: String = request.path()
+    .split('/')
+    .map(percent::encode_path_segment)
+    .collect::<Vec<_>>()
+    .join("/");
+  location.push('/');
+
+  if let Some((_, query)) = request.target().split_once('?') {
+    location.push('?');
+    location.push_str(query);
+  }
+  location
+}
+
+// Whether the path is the prefix itself or below it, /api/users is under /api but /apiary isn't.
+fn is_under(path: &str, prefix: &str) -> bool {
+  match path.strip_prefix(prefix) {
+    Some(rest) => rest.is_empty() || rest.starts_with('/'),
+    None => false
+  }
+}
+
+// Browsers list text/html in Accept when navigating to a page, while scripts
+// fetching data usually send */* or application/json.
+fn accepts_html(request: &Request) -> bool {
+  match request.headers().get("Accept") {
+    Some(accept) => negotiate::parse(accept).any(|(range, quality)| range.eq_ignore_ascii_case("text/html") && quality > 0.0),
+    None => false
+  }
+}